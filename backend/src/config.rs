@@ -0,0 +1,95 @@
+//! Configuration of the daemon.
+
+use serde::Deserialize;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Configuration of the daemon.
+///
+/// Read from [`dlm_protocol::config_path`]. Every field is optional.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Settings of the downloads, given at the top level of the file as well.
+    #[serde(flatten)]
+    pub engine: dlm_engine::Config,
+    /// File the recurring downloads are saved to.
+    /// Defaults to `$XDG_DATA_HOME/dlm_rust/recurring.json` (`$HOME/.local/share/...`).
+    pub recurring_file: Option<PathBuf>,
+    /// Socket the daemon listens on. `$DLM_SOCKET` takes precedence.
+    pub socket: Option<PathBuf>,
+    /// Users allowed to connect to the daemon, besides the one running it.
+    pub allowed_uids: Vec<u32>,
+    /// Primary groups allowed to connect to the daemon.
+    pub allowed_gids: Vec<u32>,
+    /// Give each user its own downloads, recurring downloads and download directory.
+    pub isolate_users: bool,
+    /// Also listen on TCP, for the clients on other machines.
+    pub tcp: Option<TcpConfig>,
+}
+
+/// TCP listener, next to the socket.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TcpConfig {
+    /// i.e. "0.0.0.0:7878"
+    pub address: String,
+    /// Pre-shared token the clients send with their handshake.
+    pub token: String,
+    /// Serve over TLS. Plain TCP if not set.
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain.
+    pub certificate: PathBuf,
+    /// PEM private key of the certificate, in PKCS #8.
+    pub key: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            engine: dlm_engine::Config::default(),
+            recurring_file: env::var("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| {
+                    env::var("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+                })
+                .ok()
+                .map(|dir| dir.join("dlm_rust").join("recurring.json")),
+            socket: None,
+            allowed_uids: Vec::new(),
+            allowed_gids: Vec::new(),
+            isolate_users: false,
+            tcp: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file. Defaults are used if it does not exist.
+    ///
+    /// Fails if the file cannot be read or is invalid.
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = dlm_protocol::config_path() else {
+            return Ok(Self::default());
+        };
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{path:?}: {e}"))),
+        };
+
+        serde_json::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid configuration file {path:?}: {e}"),
+            )
+        })
+    }
+}
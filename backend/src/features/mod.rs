@@ -1,2 +1,2 @@
-mod download;
-pub use download::DownloadManager;
+mod schedule;
+pub use schedule::Scheduler;
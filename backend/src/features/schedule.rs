@@ -0,0 +1,337 @@
+use chrono::DateTime;
+use chrono::Local;
+use cron::Schedule;
+use dlm_engine::filter_name;
+use dlm_engine::DownloadError;
+use dlm_protocol::DownloadOptions;
+use dlm_protocol::RecurringDownload;
+use dlm_protocol::Run;
+use tracing::error;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Runs kept in the history of a recurring download.
+const MAX_HISTORY: usize = 100;
+
+/// Parse the cron expression of the recurring download.
+fn schedule(recurring: &RecurringDownload) -> Result<Schedule, DownloadError> {
+    // The cron crate expects the seconds field as well.
+    let expression = if recurring.cron.split_whitespace().count() == 5 {
+        format!("0 {}", recurring.cron)
+    } else {
+        recurring.cron.clone()
+    };
+
+    Schedule::from_str(&expression)
+        .map_err(|e| DownloadError::Other(format!("Invalid cron expression: {e}")))
+}
+
+fn update_next_run(recurring: &mut RecurringDownload) -> Result<(), DownloadError> {
+    recurring.next_run = schedule(recurring)?.upcoming(Local).next();
+    Ok(())
+}
+
+/// Options of the download created by the next run.
+fn run_options(recurring: &RecurringDownload, now: DateTime<Local>) -> DownloadOptions {
+    let mut options = recurring.options.clone();
+
+    if let Some(template) = &recurring.destination {
+        let destination = template
+            .replace("{name}", &filter_name(recurring.url.clone()))
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{time}", &now.format("%H%M%S").to_string())
+            .replace("{run}", &recurring.runs.to_string());
+        options.destination = Some(PathBuf::from(destination));
+    }
+
+    options
+}
+
+/// Recurring downloads of the daemon, saved to a file on each change.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    path: Option<PathBuf>,
+    recurring: BTreeMap<usize, RecurringDownload>,
+}
+
+impl Scheduler {
+    /// Load the recurring downloads saved to `path`.
+    ///
+    /// A file that cannot be read is left as it is, and the changes are not saved to it.
+    pub fn load(mut path: Option<PathBuf>) -> Self {
+        let loaded = match path.as_ref().map(Self::read) {
+            Some(Err(e)) => {
+                error!("Failed to read the recurring downloads, they are not saved: {e}");
+                path = None;
+                Vec::new()
+            }
+            Some(Ok(loaded)) => loaded,
+            None => Vec::new(),
+        };
+
+        let recurring = loaded
+            .into_iter()
+            .map(|mut recurring| {
+                if let Err(e) = update_next_run(&mut recurring) {
+                    error!("Recurring download {}: {e}", recurring.id);
+                }
+                (recurring.id, recurring)
+            })
+            .collect();
+
+        Self { path, recurring }
+    }
+
+    /// Recurring downloads saved to `path`, none if it does not exist.
+    fn read(path: &PathBuf) -> io::Result<Vec<RecurringDownload>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{path:?}: {e}"))),
+        };
+
+        serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{path:?}: {e}")))
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        // Written next to it and renamed, so the file is never left half written.
+        let temporary = path.with_extension("json.tmp");
+        let recurring: Vec<&RecurringDownload> = self.recurring.values().collect();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&temporary, serde_json::to_string_pretty(&recurring)?))
+            .and_then(|_| fs::rename(&temporary, path));
+
+        if let Err(e) = result {
+            error!("Failed to save the recurring downloads to {path:?}: {e}");
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        url: String,
+        cron: String,
+        destination: Option<String>,
+        options: DownloadOptions,
+    ) -> Result<usize, DownloadError> {
+        let id = self.recurring.keys().next_back().map_or(1, |id| id + 1);
+        let mut recurring = RecurringDownload {
+            id,
+            url,
+            cron,
+            destination,
+            options,
+            next_run: None,
+            runs: 0,
+            history: Vec::new(),
+        };
+        update_next_run(&mut recurring)?;
+
+        self.recurring.insert(id, recurring);
+        self.save();
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let removed = self.recurring.remove(&id).is_some();
+        self.save();
+        removed
+    }
+
+    pub fn list(&self) -> Vec<RecurringDownload> {
+        self.recurring.values().cloned().collect()
+    }
+
+    /// Ids of the recurring downloads that are due at `now`.
+    pub fn due(&self, now: DateTime<Local>) -> Vec<usize> {
+        self.recurring
+            .values()
+            .filter(|recurring| recurring.next_run.is_some_and(|next_run| next_run <= now))
+            .map(|recurring| recurring.id)
+            .collect()
+    }
+
+    /// Start a run of the recurring download. Returns the url and options of the download to create.
+    pub fn start_run(&mut self, id: usize) -> Option<(String, DownloadOptions)> {
+        let recurring = self.recurring.get_mut(&id)?;
+
+        recurring.runs += 1;
+        let run = (recurring.url.clone(), run_options(recurring, Local::now()));
+        if let Err(e) = update_next_run(recurring) {
+            error!("Recurring download {id}: {e}");
+        }
+
+        Some(run)
+    }
+
+    /// Record the download created by a run in the history.
+    pub fn record_run(&mut self, id: usize, download_id: usize) {
+        let Some(recurring) = self.recurring.get_mut(&id) else {
+            return;
+        };
+
+        recurring.history.push(Run {
+            at: Local::now(),
+            download_id,
+        });
+        if recurring.history.len() > MAX_HISTORY {
+            recurring.history.remove(0);
+        }
+
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono::Timelike;
+
+    use super::*;
+
+    fn recurring(cron: &str, destination: Option<&str>) -> RecurringDownload {
+        RecurringDownload {
+            id: 1,
+            url: "https://example.org/nightly.iso".to_string(),
+            cron: cron.to_string(),
+            destination: destination.map(String::from),
+            options: DownloadOptions::default(),
+            next_run: None,
+            runs: 0,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cron_with_and_without_seconds() {
+        let mut daily = recurring("30 2 * * *", None);
+        update_next_run(&mut daily).unwrap();
+        let next_run = daily.next_run.unwrap();
+        assert_eq!(
+            (next_run.hour(), next_run.minute(), next_run.second()),
+            (2, 30, 0)
+        );
+
+        let mut seconds = recurring("15 30 2 * * *", None);
+        update_next_run(&mut seconds).unwrap();
+        assert_eq!(seconds.next_run.unwrap().second(), 15);
+
+        for cron in ["", "every night", "61 * * * *"] {
+            assert!(schedule(&recurring(cron, None)).is_err(), "{cron}");
+        }
+    }
+
+    #[test]
+    fn destination_template() {
+        let now = Local.with_ymd_and_hms(2024, 3, 9, 4, 5, 6).unwrap();
+        let mut nightly = recurring("0 3 * * *", Some("nightly/{date}_{time}_{run}_{name}"));
+        nightly.runs = 7;
+
+        let options = run_options(&nightly, now);
+        assert_eq!(
+            options.destination,
+            Some(PathBuf::from(
+                "nightly/2024-03-09_040506_7_httpsexampleorgnightlyiso"
+            ))
+        );
+
+        // Destination of the options is kept without a template.
+        let plain = recurring("0 3 * * *", None);
+        assert_eq!(run_options(&plain, now).destination, None);
+    }
+
+    #[test]
+    fn due_and_runs() {
+        let mut scheduler = Scheduler::default();
+        let id = scheduler
+            .add(
+                "https://example.org/a".to_string(),
+                "* * * * *".to_string(),
+                None,
+                DownloadOptions::default(),
+            )
+            .unwrap();
+        assert!(scheduler
+            .add(
+                "https://example.org/b".to_string(),
+                "never".to_string(),
+                None,
+                DownloadOptions::default(),
+            )
+            .is_err());
+
+        let next_run = scheduler.list()[0].next_run.unwrap();
+        assert!(scheduler
+            .due(next_run - chrono::Duration::seconds(1))
+            .is_empty());
+        assert_eq!(scheduler.due(next_run), [id]);
+
+        let (url, _) = scheduler.start_run(id).unwrap();
+        assert_eq!(url, "https://example.org/a");
+        scheduler.record_run(id, 42);
+
+        let recurring = &scheduler.list()[0];
+        assert_eq!(recurring.runs, 1);
+        assert_eq!(recurring.history[0].download_id, 42);
+        assert!(scheduler.start_run(id + 1).is_none());
+    }
+
+    #[test]
+    fn saved_and_loaded() {
+        let path = std::env::temp_dir()
+            .join(format!("dlm_schedule_{}", std::process::id()))
+            .join("recurring.json");
+
+        let mut scheduler = Scheduler::load(Some(path.clone()));
+        let id = scheduler
+            .add(
+                "https://example.org/a".to_string(),
+                "0 3 * * *".to_string(),
+                Some("{name}_{run}".to_string()),
+                DownloadOptions::default(),
+            )
+            .unwrap();
+        scheduler.start_run(id);
+        scheduler.record_run(id, 5);
+
+        let loaded = Scheduler::load(Some(path.clone())).list();
+        assert_eq!(loaded, scheduler.list());
+        assert!(loaded[0].next_run.is_some());
+
+        assert!(scheduler.remove(id));
+        assert!(Scheduler::load(Some(path.clone())).list().is_empty());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn invalid_file_is_kept() {
+        let path = std::env::temp_dir().join(format!("dlm_schedule_{}.json", std::process::id()));
+        fs::write(&path, "not json").unwrap();
+
+        let mut scheduler = Scheduler::load(Some(path.clone()));
+        assert!(scheduler.list().is_empty());
+
+        scheduler
+            .add(
+                "https://example.org/a".to_string(),
+                "0 3 * * *".to_string(),
+                None,
+                DownloadOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not json");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
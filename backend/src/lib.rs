@@ -0,0 +1,516 @@
+//! Daemon serving the downloads to its clients, over any [`Transport`].
+
+use dlm_engine::DownloadError;
+use dlm_engine::DownloadManager;
+use dlm_protocol::socket_path;
+use dlm_protocol::stdio;
+use dlm_protocol::Command;
+use dlm_protocol::ErrorCode;
+use dlm_protocol::Rejected;
+use dlm_protocol::Request;
+use dlm_protocol::Response;
+use dlm_protocol::ServerMessage;
+use dlm_protocol::Transport;
+use dlm_protocol::MIN_PROTOCOL_VERSION;
+use dlm_protocol::PROTOCOL_VERSION;
+use features::Scheduler;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use users::Users;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use config::Config;
+pub use config::TcpConfig;
+pub use config::TlsConfig;
+
+mod config;
+mod features;
+mod socket;
+mod tcp;
+mod users;
+
+/// Time a client sending a token has to complete its handshake in. Applies to TLS as well.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request a client can send, in bytes. The connection is closed after a longer one.
+const MAX_LINE: usize = 1024 * 1024;
+
+/// Send a message to the client as a single line.
+async fn send<W: AsyncWrite + Unpin>(
+    writer: &Mutex<W>,
+    message: &ServerMessage,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message).unwrap();
+    line.push(b'\n');
+
+    writer.lock().await.write_all(&line).await
+}
+
+/// `request_id` of a request that could not be read, if it has one.
+fn request_id_of(input: &[u8]) -> Option<u64> {
+    serde_json::from_slice::<serde_json::Value>(input)
+        .ok()?
+        .get("request_id")?
+        .as_u64()
+}
+
+/// Whether the token sent by the client is the required one, if any.
+fn authorized(required: Option<&str>, given: Option<&str>) -> bool {
+    let Some(required) = required else {
+        return true;
+    };
+    let given = given.unwrap_or_default();
+
+    // Compared without stopping at the first difference, so the time taken
+    // does not tell how much of the token is right.
+    required.len() == given.len()
+        && required
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Tell the client its user is not allowed to use the daemon, and close the connection.
+async fn refuse(mut stream: UnixStream) {
+    let message = ServerMessage::Error {
+        request_id: None,
+        code: ErrorCode::Unauthorized,
+        message: "User is not allowed to use the daemon".to_string(),
+    };
+    let mut line = serde_json::to_vec(&message).unwrap();
+    line.push(b'\n');
+
+    // Nothing to do if the client is already gone.
+    let _ = stream.write_all(&line).await;
+}
+
+/// Downloads and recurring downloads, served to the clients.
+#[derive(Clone)]
+pub struct SharedState {
+    download_manager: DownloadManager,
+    scheduler: Arc<Mutex<Scheduler>>,
+}
+
+impl SharedState {
+    pub fn new(config: Config) -> Self {
+        Self {
+            scheduler: Arc::new(Mutex::new(Scheduler::load(config.recurring_file.clone()))),
+            download_manager: DownloadManager::new(config.engine),
+        }
+    }
+
+    /// Create the state and start running its recurring downloads.
+    pub fn start(config: Config) -> Self {
+        let state = Self::new(config);
+        tokio::spawn(state.clone().run_scheduler());
+        state
+    }
+
+    /// Check that the destination, if any, is a path inside the download directory.
+    fn check_destination(&self, destination: Option<&Path>) -> Result<(), DownloadError> {
+        destination.map_or(Ok(()), |destination| {
+            self.download_manager.check_destination(destination)
+        })
+    }
+
+    /// Create the download of a recurring download's run and start it.
+    ///
+    /// Returns the id of the download.
+    async fn run_recurring(&self, id: usize) -> Result<usize, DownloadError> {
+        let Some((url, options)) = self.scheduler.lock().await.start_run(id) else {
+            return Err(DownloadError::Other(format!(
+                "No recurring download with id {id}"
+            )));
+        };
+
+        // Policy could have changed since the recurring download was added.
+        self.download_manager.check_url(&url).await?;
+
+        let download_ids = self.download_manager.enqueue(vec![url], options).await;
+
+        let Some(download_id) = download_ids.first().copied() else {
+            return Err(DownloadError::Other(
+                "Url of the recurring download is already downloading".to_string(),
+            ));
+        };
+        info!("Recurring download {id} started as {download_id}");
+        self.scheduler.lock().await.record_run(id, download_id);
+
+        Ok(download_id)
+    }
+
+    /// Start the runs of recurring downloads when they are due.
+    pub async fn run_scheduler(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            interval.tick().await;
+
+            let due = self.scheduler.lock().await.due(chrono::Local::now());
+            for id in due {
+                if let Err(e) = self.run_recurring(id).await {
+                    warn!("Skipped the run of recurring download {id}: {e}");
+                }
+            }
+        }
+    }
+
+    /// Send the progress of the downloads back to the client.
+    async fn forward_progress<W>(&self, writer: Arc<Mutex<W>>) -> JoinHandle<()>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut rx = self.download_manager.subscribe();
+        let dm = self.download_manager.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let message = match rx.recv().await {
+                    Ok(download) => ServerMessage::Download {
+                        download: Box::new(download),
+                    },
+                    // Client is too slow to keep up. Skipped changes are
+                    // replaced with the current state of every download.
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Client lagged behind by {skipped} events");
+                        ServerMessage::Snapshot {
+                            downloads: dm.list_downloads().await,
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                if let Err(e) = send(&writer, &message).await {
+                    error!("Error occured on sending download info: {e:#?}");
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Carry out the command of a request.
+    async fn execute(&self, command: Command) -> Result<Response, DownloadError> {
+        match command {
+            Command::Hello { .. } => Err(DownloadError::Other(
+                "Handshake is already done".to_string(),
+            )),
+
+            Command::Download { urls, options } => {
+                if options.destination.is_some() && urls.len() > 1 {
+                    return Err(DownloadError::Other(
+                        "Destination can be given for a single url only".to_string(),
+                    ));
+                }
+                self.check_destination(options.destination.as_deref())?;
+
+                let dm = &self.download_manager;
+                let mut accepted = Vec::new();
+                let mut rejected = Vec::new();
+
+                for url in urls {
+                    match dm.check_url(&url).await {
+                        Ok(()) => accepted.push(url),
+                        Err(e) => {
+                            warn!("Rejected the url {url}: {e}");
+                            let message = e.message();
+                            rejected.push(Rejected { url, message });
+                        }
+                    }
+                }
+
+                let ids = dm.enqueue(accepted, options).await;
+                Ok(Response::Added { ids, rejected })
+            }
+
+            Command::Pause { id } => {
+                self.download_manager.pause_downloading(id).await?;
+                Ok(Response::Done)
+            }
+
+            Command::Resume { id } => {
+                self.download_manager.resume_download(id).await?;
+                Ok(Response::Done)
+            }
+
+            Command::UpdateUrl { id, url } => {
+                self.download_manager.update_url(id, url).await?;
+                Ok(Response::Done)
+            }
+
+            Command::Retry { id } => {
+                self.download_manager.retry_download(id).await?;
+                Ok(Response::Done)
+            }
+
+            Command::Cancel { id, delete } => {
+                self.download_manager.cancel_downloading(id, delete).await?;
+                Ok(Response::Done)
+            }
+
+            Command::List => Ok(Response::Downloads {
+                downloads: self.download_manager.list_downloads().await,
+            }),
+
+            Command::AddRecurring {
+                url,
+                cron,
+                destination,
+                options,
+            } => {
+                self.check_destination(options.destination.as_deref())?;
+                self.check_destination(destination.as_deref().map(Path::new))?;
+                self.download_manager.check_url(&url).await?;
+
+                let id = self
+                    .scheduler
+                    .lock()
+                    .await
+                    .add(url, cron, destination, options)?;
+                info!("Recurring download {id} is added");
+                Ok(Response::RecurringAdded { id })
+            }
+
+            Command::RemoveRecurring { id } => {
+                if !self.scheduler.lock().await.remove(id) {
+                    return Err(DownloadError::Other(format!(
+                        "No recurring download with id {id}"
+                    )));
+                }
+                Ok(Response::Done)
+            }
+
+            Command::TriggerRecurring { id } => {
+                let download_id = self.run_recurring(id).await?;
+                Ok(Response::Triggered { download_id })
+            }
+
+            Command::ListRecurring => Ok(Response::Recurring {
+                recurring: self.scheduler.lock().await.list(),
+            }),
+        }
+    }
+
+    /// Version to use with a client speaking up to `version`. The newest one spoken by both.
+    fn negotiate(version: u32) -> Option<u32> {
+        let version = version.min(PROTOCOL_VERSION);
+        (version >= MIN_PROTOCOL_VERSION).then_some(version)
+    }
+
+    /// Serve the client until it disconnects. `token` is required with its handshake, if set.
+    pub async fn handle_connection(self, transport: impl Transport, token: Option<Arc<str>>) {
+        let (reader_half, writer_half) = transport.into_split();
+        let mut reader = tokio::io::BufReader::new(reader_half);
+        let mut input = Vec::new();
+
+        let writer = Arc::new(Mutex::new(writer_half));
+        // Started by the handshake. Every client gets the changes of all the
+        // downloads, until it disconnects.
+        let mut progress_task: Option<JoinHandle<()>> = None;
+        // Clients have to prove they know the token before long.
+        let handshake_deadline = token
+            .is_some()
+            .then(|| tokio::time::Instant::now() + HANDSHAKE_TIMEOUT);
+
+        loop {
+            let mut limited = (&mut reader).take(MAX_LINE as u64 + 1);
+            let read = limited.read_until(b'\n', &mut input);
+            let read = match handshake_deadline.filter(|_| progress_task.is_none()) {
+                Some(deadline) => match tokio::time::timeout_at(deadline, read).await {
+                    Ok(read) => read,
+                    Err(_) => {
+                        warn!("Client did not complete the handshake in time");
+                        break;
+                    }
+                },
+                None => read.await,
+            };
+
+            match read {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to read from the client: {e}");
+                    break;
+                }
+            }
+
+            if input.len() > MAX_LINE {
+                warn!("Client sent a request longer than {MAX_LINE} bytes");
+                let refusal = ServerMessage::Error {
+                    request_id: None,
+                    code: ErrorCode::InvalidRequest,
+                    message: format!("Request is longer than {MAX_LINE} bytes"),
+                };
+                // Rest of the line cannot be told apart from the next request.
+                let _ = send(&writer, &refusal).await;
+                break;
+            }
+
+            let request =
+                serde_json::from_slice::<Request>(&input).map_err(|e| (request_id_of(&input), e));
+            input.clear();
+
+            let message = match request {
+                Ok(Request {
+                    request_id,
+                    command: Command::Hello { token: given, .. },
+                }) if progress_task.is_none()
+                    && !authorized(token.as_deref(), given.as_deref()) =>
+                {
+                    warn!("Refused a client with a wrong token");
+                    let refusal = ServerMessage::Error {
+                        request_id: Some(request_id),
+                        code: ErrorCode::Unauthorized,
+                        message: "Wrong token".to_string(),
+                    };
+                    // Connection is closed either way.
+                    let _ = send(&writer, &refusal).await;
+                    break;
+                }
+
+                Ok(Request {
+                    request_id,
+                    command: Command::Hello { version, .. },
+                }) if progress_task.is_none() => match Self::negotiate(version) {
+                    Some(version) => {
+                        let welcome = ServerMessage::Reply {
+                            request_id,
+                            response: Response::Welcome { version },
+                        };
+                        if send(&writer, &welcome).await.is_err() {
+                            break;
+                        }
+
+                        // Events are sent only after the reply to the handshake.
+                        progress_task = Some(self.forward_progress(Arc::clone(&writer)).await);
+                        continue;
+                    }
+                    None => ServerMessage::Error {
+                        request_id: Some(request_id),
+                        code: ErrorCode::UnsupportedVersion,
+                        message: format!(
+                            "Versions {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION} are supported"
+                        ),
+                    },
+                },
+
+                Ok(Request { request_id, .. }) if progress_task.is_none() => ServerMessage::Error {
+                    request_id: Some(request_id),
+                    code: ErrorCode::HandshakeRequired,
+                    message: "Send Hello first".to_string(),
+                },
+
+                Ok(Request {
+                    request_id,
+                    command,
+                }) => match self.execute(command).await {
+                    Ok(response) => ServerMessage::Reply {
+                        request_id,
+                        response,
+                    },
+                    Err(e) => ServerMessage::Error {
+                        request_id: Some(request_id),
+                        code: e.code(),
+                        message: e.message(),
+                    },
+                },
+
+                Err((request_id, e)) => ServerMessage::Error {
+                    request_id,
+                    code: ErrorCode::InvalidRequest,
+                    message: e.to_string(),
+                },
+            };
+
+            if let Err(e) = send(&writer, &message).await {
+                warn!("Failed to reply to the client: {e}");
+                break;
+            }
+        }
+
+        if let Some(progress_task) = progress_task {
+            progress_task.abort();
+        }
+        info!("Client disconnected");
+    }
+}
+
+/// Serve the clients connecting to the socket, and over TCP if configured.
+///
+/// Exits the process if it cannot listen.
+pub async fn run(config: Config) {
+    let path = socket_path(config.socket.clone());
+    let shared = !config.allowed_uids.is_empty() || !config.allowed_gids.is_empty();
+    let socket = match socket::listen(&path, shared) {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!("Could not listen on {path:?}: {e}");
+            std::process::exit(1);
+        }
+    };
+    info!("Listening on {path:?}");
+
+    let tcp = config.tcp.clone();
+    let users = Users::new(config, socket.owner);
+
+    if let Some(tcp) = tcp {
+        let remote = match tcp::listen(&tcp).await {
+            Ok(remote) => remote,
+            Err(e) => {
+                error!("Could not listen on {}: {e}", tcp.address);
+                std::process::exit(1);
+            }
+        };
+        info!("Listening on {}", tcp.address);
+
+        tokio::spawn(remote.serve(users.remote().await));
+    }
+
+    while let Ok((stream, _)) = socket.listener.accept().await {
+        let peer = match stream.peer_cred() {
+            Ok(peer) => peer,
+            Err(e) => {
+                warn!("Failed to get the credentials of the client: {e}");
+                continue;
+            }
+        };
+
+        if !users.allows(&peer) {
+            warn!(
+                "Refused the client of user {} and group {}",
+                peer.uid(),
+                peer.gid()
+            );
+            tokio::spawn(refuse(stream));
+            continue;
+        }
+
+        let state = users.queue(peer.uid()).await;
+        tokio::spawn(state.handle_connection(stream, None));
+    }
+}
+
+/// Serve a single client over the standard input and output, until it is closed.
+///
+/// For running the daemon as a subprocess of its client, which is trusted. Its recurring
+/// downloads are not saved, the recurring file belongs to the daemon listening on the socket.
+pub async fn run_stdio(config: Config) {
+    let config = Config {
+        recurring_file: None,
+        ..config
+    };
+    SharedState::start(config)
+        .handle_connection(stdio(), None)
+        .await;
+}
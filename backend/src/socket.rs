@@ -0,0 +1,142 @@
+//! Unix socket the daemon listens on.
+
+use tokio::net::UnixListener;
+use tracing::warn;
+
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::File;
+use std::fs::TryLockError;
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Listening socket, and the lock keeping a second daemon from taking it over.
+pub struct Socket {
+    pub listener: UnixListener,
+    /// Uid of the user running the daemon.
+    pub owner: u32,
+    /// Released once the daemon exits and the file is closed.
+    _lock: File,
+}
+
+/// Listen on the socket at `path`, accessible by the owner only unless it is `shared`
+/// with the other users. Shared sockets rely on the peer credentials instead.
+///
+/// Fails if another daemon holds the lock file next to the socket, or still
+/// answers on it. A socket left behind by a daemon that is gone is replaced.
+/// Fails as well if the directory of the socket could be changed by other users.
+pub fn listen(path: &Path, shared: bool) -> io::Result<Socket> {
+    let (dir_mode, socket_mode) = if shared {
+        (0o711, 0o666)
+    } else {
+        (0o700, 0o600)
+    };
+
+    // Mode is only set if the directory is created, an existing one is checked instead.
+    if let Some(dir) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(dir_mode)
+            .create(dir)?;
+        check_dir(dir)?;
+    }
+
+    let lock = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(path))?;
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "Another daemon is running",
+            ));
+        }
+        Err(TryLockError::Error(e)) => return Err(e),
+    }
+
+    remove_stale(path)?;
+
+    // Socket is created with its permissions, nobody else can connect before they are set.
+    // Umask is per process, so nothing else may create files meanwhile.
+    // SAFETY: umask only changes the mode mask of the process, and can not fail.
+    let umask = unsafe { libc::umask(!socket_mode & 0o777) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+
+    Ok(Socket {
+        listener: listener?,
+        owner: effective_uid(),
+        _lock: lock,
+    })
+}
+
+fn effective_uid() -> u32 {
+    // SAFETY: geteuid can not fail.
+    unsafe { libc::geteuid() }
+}
+
+/// Check that only the user running the daemon can change the directory of the socket.
+///
+/// i.e. `/tmp/dlm_rust` could have been created by another user before the daemon.
+fn check_dir(dir: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+
+    let problem = if !metadata.is_dir() {
+        "is not a directory"
+    } else if metadata.uid() != effective_uid() {
+        "is owned by another user"
+    } else if metadata.mode() & 0o022 != 0 {
+        "is writable by other users"
+    } else {
+        return Ok(());
+    };
+
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("Directory of the socket {dir:?} {problem}"),
+    ))
+}
+
+/// i.e. "SOCKET" -> "SOCKET.lock"
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Remove the socket left behind by a daemon that is gone.
+fn remove_stale(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{path:?} exists and is not a socket"),
+        ));
+    }
+
+    // i.e. A daemon started without the lock file.
+    match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "Another daemon is listening on the socket",
+        )),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            warn!("Replacing the stale socket {path:?}");
+            fs::remove_file(path)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,95 @@
+//! TCP listener for the clients on other machines, optionally over TLS.
+
+use tokio::net::TcpListener;
+use tokio_native_tls::native_tls;
+use tokio_native_tls::native_tls::Identity;
+use tokio_native_tls::TlsAcceptor;
+use tracing::{info, warn};
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use crate::SharedState;
+use crate::TcpConfig;
+use crate::TlsConfig;
+use crate::HANDSHAKE_TIMEOUT;
+
+pub struct Remote {
+    listener: TcpListener,
+    /// Handshake of the TLS connections. Plain TCP if None.
+    tls: Option<TlsAcceptor>,
+    /// Required with the handshake of every client.
+    token: Arc<str>,
+}
+
+/// Listen on the address of the configuration.
+///
+/// Fails if the token is empty, or the certificate cannot be loaded.
+pub async fn listen(config: &TcpConfig) -> io::Result<Remote> {
+    if config.token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The token of the TCP listener is empty",
+        ));
+    }
+
+    let tls = config.tls.as_ref().map(acceptor).transpose()?;
+    let listener = TcpListener::bind(&config.address).await?;
+
+    Ok(Remote {
+        listener,
+        tls,
+        token: Arc::from(config.token.as_str()),
+    })
+}
+
+/// Load the certificate and its key.
+fn acceptor(config: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let certificate = fs::read(&config.certificate)?;
+    let key = fs::read(&config.key)?;
+
+    let identity = Identity::from_pkcs8(&certificate, &key).map_err(io::Error::other)?;
+    let acceptor = native_tls::TlsAcceptor::new(identity).map_err(io::Error::other)?;
+
+    Ok(acceptor.into())
+}
+
+impl Remote {
+    /// Serve the clients with the downloads of `state`.
+    pub async fn serve(self, state: SharedState) {
+        loop {
+            let (stream, address) = match self.listener.accept().await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Failed to accept a TCP client: {e}");
+                    continue;
+                }
+            };
+            info!("Client connected from {address}");
+
+            let state = state.clone();
+            let token = Some(Arc::clone(&self.token));
+
+            match &self.tls {
+                Some(tls) => {
+                    let tls = tls.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+                            Ok(Ok(stream)) => {
+                                let (reader, writer) = tokio::io::split(stream);
+                                let transport = tokio::io::join(reader, writer);
+                                state.handle_connection(transport, token).await
+                            }
+                            Ok(Err(e)) => warn!("TLS handshake with {address} failed: {e}"),
+                            Err(_) => warn!("TLS handshake with {address} timed out"),
+                        }
+                    });
+                }
+                None => {
+                    tokio::spawn(state.handle_connection(stream, token));
+                }
+            }
+        }
+    }
+}
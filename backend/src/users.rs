@@ -0,0 +1,107 @@
+//! Users allowed to use the daemon, and their downloads.
+
+use tokio::net::unix::UCred;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::Config;
+use crate::SharedState;
+
+#[derive(Clone)]
+pub struct Users {
+    config: Arc<Config>,
+    /// Uid of the user running the daemon, always allowed.
+    owner: u32,
+    /// Queue of every user, unless the users are isolated.
+    shared: Option<SharedState>,
+    /// Queue of each user, once the user connected or if it has recurring downloads.
+    queues: Arc<Mutex<HashMap<u32, SharedState>>>,
+}
+
+impl Users {
+    pub fn new(config: Config, owner: u32) -> Self {
+        let mut queues = HashMap::new();
+        let shared = if config.isolate_users {
+            // Recurring downloads run without their user connecting.
+            for uid in saved_users(&config) {
+                queues.insert(uid, SharedState::start(user_config(&config, uid)));
+            }
+            None
+        } else {
+            Some(SharedState::start(config.clone()))
+        };
+
+        Self {
+            config: Arc::new(config),
+            owner,
+            shared,
+            queues: Arc::new(Mutex::new(queues)),
+        }
+    }
+
+    /// Whether the peer may use the daemon: the user running it, or an allowed user or primary group.
+    pub fn allows(&self, peer: &UCred) -> bool {
+        peer.uid() == self.owner
+            || self.config.allowed_uids.contains(&peer.uid())
+            || self.config.allowed_gids.contains(&peer.gid())
+    }
+
+    /// Downloads of the user.
+    pub async fn queue(&self, uid: u32) -> SharedState {
+        if let Some(shared) = &self.shared {
+            return shared.clone();
+        }
+
+        self.queues
+            .lock()
+            .await
+            .entry(uid)
+            .or_insert_with(|| {
+                info!("Created the queue of user {uid}");
+                SharedState::start(user_config(&self.config, uid))
+            })
+            .clone()
+    }
+
+    /// Downloads of the clients connecting over TCP, who act as the user running the daemon.
+    pub async fn remote(&self) -> SharedState {
+        self.queue(self.owner).await
+    }
+}
+
+/// Configuration of the user's own queue, with its own download directory and recurring downloads.
+fn user_config(config: &Config, uid: u32) -> Config {
+    let mut config = config.clone();
+    config.engine.download_dir = Some(config.engine.download_dir().join(uid.to_string()));
+    config.recurring_file = config.recurring_file.as_deref().map(|path| {
+        users_dir(path)
+            .join(uid.to_string())
+            .join(path.file_name().unwrap_or_default())
+    });
+    config
+}
+
+/// i.e. ".../dlm_rust/recurring.json" -> ".../dlm_rust/users"
+fn users_dir(recurring_file: &Path) -> PathBuf {
+    recurring_file.with_file_name("users")
+}
+
+/// Users whose recurring downloads are saved.
+fn saved_users(config: &Config) -> Vec<u32> {
+    let Some(recurring_file) = &config.recurring_file else {
+        return Vec::new();
+    };
+
+    fs::read_dir(users_dir(recurring_file))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect()
+}
@@ -1,8 +0,0 @@
-mod download_error;
-mod os_config;
-mod util;
-
-pub use download_error::DownloadError;
-pub use os_config::os_download_dir;
-pub use util::filter_name;
-pub use util::validate_url;
@@ -0,0 +1,224 @@
+//! The server and the client talking over in-memory pipes.
+
+use dlm_control::Client;
+use dlm_control::ClientError;
+use dlm_protocol::DownloadOptions;
+use dlm_protocol::ErrorCode;
+use dlm_protocol::Response;
+use dlm_protocol::ServerMessage;
+use dlm_protocol::PROTOCOL_VERSION;
+use dlm_server::Config;
+use dlm_server::SharedState;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::io::DuplexStream;
+use tokio::sync::broadcast::error::RecvError;
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Serve a client at the other end of the returned pipe. `token` is required if set.
+fn serve(token: Option<&str>) -> DuplexStream {
+    let config = Config {
+        engine: dlm_engine::Config {
+            download_dir: Some(env::temp_dir().join("dlm_pipe")),
+            ..Default::default()
+        },
+        recurring_file: None,
+        ..Default::default()
+    };
+
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(SharedState::new(config).handle_connection(server, token.map(Arc::from)));
+    client
+}
+
+#[tokio::test]
+async fn commands() {
+    let client = Client::connect_with(serve(None), None).await.unwrap();
+    assert_eq!(client.version(), PROTOCOL_VERSION);
+    assert!(client.list().await.unwrap().is_empty());
+
+    let url = "ftp://example.org/file.iso".to_string();
+    let (ids, rejected) = client
+        .add(vec![url.clone()], DownloadOptions::default())
+        .await
+        .unwrap();
+    assert!(ids.is_empty());
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].url, url);
+
+    assert!(matches!(
+        client.pause(7).await,
+        Err(ClientError::Server {
+            code: ErrorCode::Failed,
+            ..
+        })
+    ));
+
+    let id = client
+        .add_recurring(
+            "https://example.org/nightly.iso".to_string(),
+            "0 3 * * *".to_string(),
+            None,
+            DownloadOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(client.list_recurring().await.unwrap().len(), 1);
+
+    client.remove_recurring(id).await.unwrap();
+    assert!(client.list_recurring().await.unwrap().is_empty());
+
+    for destination in ["/etc/cron.d/file", "../file"] {
+        let options = DownloadOptions {
+            destination: Some(PathBuf::from(destination)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            client
+                .add(vec!["https://example.org/file".to_string()], options)
+                .await,
+            Err(ClientError::Server {
+                code: ErrorCode::Rejected,
+                ..
+            })
+        ));
+    }
+
+    let options = DownloadOptions {
+        destination: Some(PathBuf::from("file")),
+        ..Default::default()
+    };
+    let urls = vec![
+        "https://example.org/a".to_string(),
+        "https://example.org/b".to_string(),
+    ];
+    assert!(matches!(
+        client.add(urls, options).await,
+        Err(ClientError::Server {
+            code: ErrorCode::Failed,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn token() {
+    for token in [None, Some("wrong".to_string())] {
+        assert!(matches!(
+            Client::connect_with(serve(Some("secret")), token).await,
+            Err(ClientError::Server {
+                code: ErrorCode::Unauthorized,
+                ..
+            })
+        ));
+    }
+
+    let client = Client::connect_with(serve(Some("secret")), Some("secret".to_string()))
+        .await
+        .unwrap();
+    assert!(client.list().await.unwrap().is_empty());
+}
+
+/// Requests the client would not send.
+#[tokio::test]
+async fn invalid_requests() {
+    let (reader, mut writer) = tokio::io::split(serve(None));
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut exchange = async |request: &str| {
+        writer
+            .write_all(format!("{request}\n").as_bytes())
+            .await
+            .unwrap();
+        let reply = lines.next_line().await.unwrap().unwrap();
+        serde_json::from_str::<ServerMessage>(&reply).unwrap()
+    };
+
+    assert!(matches!(
+        exchange(r#"{"request_id": 1, "command": "List"}"#).await,
+        ServerMessage::Error {
+            request_id: Some(1),
+            code: ErrorCode::HandshakeRequired,
+            ..
+        }
+    ));
+
+    assert_eq!(
+        exchange(r#"{"request_id": 2, "command": "Hello", "version": 1}"#).await,
+        ServerMessage::Reply {
+            request_id: 2,
+            response: Response::Welcome { version: 1 },
+        }
+    );
+
+    assert!(matches!(
+        exchange(r#"{"request_id": 3, "command": "Explode"}"#).await,
+        ServerMessage::Error {
+            request_id: Some(3),
+            code: ErrorCode::InvalidRequest,
+            ..
+        }
+    ));
+
+    assert!(matches!(
+        exchange("not json").await,
+        ServerMessage::Error {
+            request_id: None,
+            code: ErrorCode::InvalidRequest,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn long_request() {
+    let (reader, mut writer) = tokio::io::split(serve(None));
+    let mut lines = BufReader::new(reader).lines();
+
+    // Server stops reading partway, the rest of the write fails.
+    tokio::spawn(async move {
+        let request = format!("{}\n", "x".repeat(2 * 1024 * 1024));
+        let _ = writer.write_all(request.as_bytes()).await;
+    });
+
+    let reply = lines.next_line().await.unwrap().unwrap();
+    assert!(matches!(
+        serde_json::from_str::<ServerMessage>(&reply).unwrap(),
+        ServerMessage::Error {
+            request_id: None,
+            code: ErrorCode::InvalidRequest,
+            ..
+        }
+    ));
+    assert!(lines.next_line().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn events_closed_with_the_connection() {
+    let (server, client) = tokio::io::duplex(64 * 1024);
+
+    // Server welcoming the client, then gone.
+    tokio::spawn(async move {
+        let (reader, mut writer) = tokio::io::split(server);
+        let hello = BufReader::new(reader).lines().next_line().await.unwrap();
+        assert!(hello.is_some());
+        let welcome = ServerMessage::Reply {
+            request_id: 0,
+            response: Response::Welcome {
+                version: PROTOCOL_VERSION,
+            },
+        };
+        let line = format!("{}\n", serde_json::to_string(&welcome).unwrap());
+        writer.write_all(line.as_bytes()).await.unwrap();
+    });
+
+    let client = Client::connect_with(client, None).await.unwrap();
+    assert!(matches!(
+        client.subscribe().recv().await,
+        Err(RecvError::Closed)
+    ));
+}
@@ -0,0 +1,388 @@
+use dlm_protocol::Command;
+use dlm_protocol::DownloadOptions;
+use dlm_protocol::DownloadSnapshot;
+use dlm_protocol::RecurringDownload;
+use dlm_protocol::Rejected;
+use dlm_protocol::Request;
+use dlm_protocol::Response;
+use dlm_protocol::ServerMessage;
+use dlm_protocol::Transport;
+use dlm_protocol::PROTOCOL_VERSION;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpStream;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast;
+use tokio::sync::oneshot;
+use tokio::sync::Mutex;
+use tokio_native_tls::native_tls;
+use tokio_native_tls::native_tls::Certificate;
+use tokio_native_tls::TlsConnector;
+use tracing::{error, warn};
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::ClientError;
+
+/// Events kept for the subscribers. A subscriber falling further behind lags.
+const EVENTS_CAPACITY: usize = 1024;
+
+type Reply = oneshot::Sender<Result<Response, ClientError>>;
+
+/// Requests waiting for their reply, by `request_id`. None once the connection is closed.
+type Pending = Arc<std::sync::Mutex<Option<HashMap<u64, Reply>>>>;
+
+/// Write half of the connection, whatever it runs over.
+type Writer = Arc<Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+
+/// Change of the downloads, sent by the server.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A download changed.
+    Download(Box<DownloadSnapshot>),
+    /// Current state of every download, sent when the client fell behind the changes.
+    Snapshot(Vec<DownloadSnapshot>),
+}
+
+/// Connection to `dlm_server`.
+///
+/// Clones share the connection, so requests can be made from several tasks at once.
+#[derive(Clone)]
+pub struct Client {
+    writer: Writer,
+    next_request_id: Arc<AtomicU64>,
+    pending: Pending,
+    /// Only kept to subscribe from. The reading task has the only sender, so the
+    /// subscribers are closed once the connection is.
+    events: Arc<broadcast::Receiver<Event>>,
+    version: u32,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Connect to the server listening on `path` and make the handshake.
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let stream = UnixStream::connect(path).await?;
+        Self::connect_with(stream, None).await
+    }
+
+    /// Connect to the server listening on TCP at `address`, i.e. "download-box:7878",
+    /// and make the handshake with its token.
+    pub async fn connect_tcp(address: &str, token: &str) -> Result<Self, ClientError> {
+        let stream = TcpStream::connect(address).await?;
+        Self::connect_with(stream, Some(token.to_string())).await
+    }
+
+    /// Same as [`Client::connect_tcp`], over TLS.
+    ///
+    /// The certificate of the server has to be trusted by the system, or be `certificate`
+    /// (PEM), i.e. a self-signed one.
+    pub async fn connect_tls(
+        address: &str,
+        token: &str,
+        certificate: Option<&Path>,
+    ) -> Result<Self, ClientError> {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(certificate) = certificate {
+            builder.add_root_certificate(Certificate::from_pem(&fs::read(certificate)?)?);
+        }
+        let connector = TlsConnector::from(builder.build()?);
+
+        let stream = TcpStream::connect(address).await?;
+        let stream = connector.connect(host(address), stream).await?;
+        let (reader, writer) = tokio::io::split(stream);
+        Self::connect_with(tokio::io::join(reader, writer), Some(token.to_string())).await
+    }
+
+    /// Start `program` (i.e. "dlm_server") as a subprocess serving over its standard
+    /// input and output, and make the handshake.
+    ///
+    /// The subprocess exits once the last clone of the client is dropped.
+    pub async fn spawn(program: impl AsRef<OsStr>) -> Result<Self, ClientError> {
+        let mut child = tokio::process::Command::new(program)
+            .arg("--stdio")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("Standard input is piped");
+        let stdout = child.stdout.take().expect("Standard output is piped");
+        Self::connect_with(tokio::io::join(stdout, stdin), None).await
+    }
+
+    /// Make the handshake with the server at the other end of `transport`.
+    /// `token` is only required over TCP.
+    pub async fn connect_with(
+        transport: impl Transport,
+        token: Option<String>,
+    ) -> Result<Self, ClientError> {
+        let (read_half, write_half) = transport.into_split();
+        let pending: Pending = Arc::new(std::sync::Mutex::new(Some(HashMap::new())));
+        let (events_tx, events) = broadcast::channel(EVENTS_CAPACITY);
+
+        tokio::spawn(read_messages(read_half, Arc::clone(&pending), events_tx));
+
+        let mut client = Self {
+            writer: Arc::new(Mutex::new(Box::new(write_half))),
+            next_request_id: Arc::new(AtomicU64::new(0)),
+            pending,
+            events: Arc::new(events),
+            version: PROTOCOL_VERSION,
+        };
+
+        let hello = Command::Hello {
+            version: PROTOCOL_VERSION,
+            token,
+        };
+        client.version = match client.request(hello).await? {
+            Response::Welcome { version } => version,
+            response => return Err(ClientError::UnexpectedResponse(Box::new(response))),
+        };
+
+        Ok(client)
+    }
+
+    /// Version of the protocol agreed on with the server.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Send the command and wait for its reply.
+    pub async fn request(&self, command: Command) -> Result<Response, ClientError> {
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        // Registered before sending, the reply can arrive before the write returns.
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(request_id, reply_tx),
+            None => return Err(ClientError::Disconnected),
+        };
+
+        let mut line = serde_json::to_vec(&Request {
+            request_id,
+            command,
+        })
+        .unwrap();
+        line.push(b'\n');
+
+        if let Err(e) = self.writer.lock().await.write_all(&line).await {
+            if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                pending.remove(&request_id);
+            }
+            return Err(e.into());
+        }
+
+        reply_rx.await.map_err(|_| ClientError::Disconnected)?
+    }
+
+    /// Send a command replied with [`Response::Done`].
+    async fn done(&self, command: Command) -> Result<(), ClientError> {
+        match self.request(command).await? {
+            Response::Done => Ok(()),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Add the downloads of the urls. Returns the ids of the added ones, and the rejected urls.
+    pub async fn add(
+        &self,
+        urls: Vec<String>,
+        options: DownloadOptions,
+    ) -> Result<(Vec<usize>, Vec<Rejected>), ClientError> {
+        match self.request(Command::Download { urls, options }).await? {
+            Response::Added { ids, rejected } => Ok((ids, rejected)),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    pub async fn pause(&self, id: usize) -> Result<(), ClientError> {
+        self.done(Command::Pause { id }).await
+    }
+
+    pub async fn resume(&self, id: usize) -> Result<(), ClientError> {
+        self.done(Command::Resume { id }).await
+    }
+
+    pub async fn retry(&self, id: usize) -> Result<(), ClientError> {
+        self.done(Command::Retry { id }).await
+    }
+
+    /// Cancel the download. If `delete` is true the partially downloaded file is removed as well.
+    pub async fn cancel(&self, id: usize, delete: bool) -> Result<(), ClientError> {
+        self.done(Command::Cancel { id, delete }).await
+    }
+
+    /// Replace the url of the download, which has to serve the same file.
+    pub async fn update_url(&self, id: usize, url: String) -> Result<(), ClientError> {
+        self.done(Command::UpdateUrl { id, url }).await
+    }
+
+    /// Current state of every download.
+    pub async fn list(&self) -> Result<Vec<DownloadSnapshot>, ClientError> {
+        match self.request(Command::List).await? {
+            Response::Downloads { downloads } => Ok(downloads),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Add a url downloaded on the cron schedule. Returns the id of the recurring download.
+    pub async fn add_recurring(
+        &self,
+        url: String,
+        cron: String,
+        destination: Option<String>,
+        options: DownloadOptions,
+    ) -> Result<usize, ClientError> {
+        let command = Command::AddRecurring {
+            url,
+            cron,
+            destination,
+            options,
+        };
+
+        match self.request(command).await? {
+            Response::RecurringAdded { id } => Ok(id),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    pub async fn remove_recurring(&self, id: usize) -> Result<(), ClientError> {
+        self.done(Command::RemoveRecurring { id }).await
+    }
+
+    /// Run the recurring download now. Returns the id of the started download.
+    pub async fn trigger_recurring(&self, id: usize) -> Result<usize, ClientError> {
+        match self.request(Command::TriggerRecurring { id }).await? {
+            Response::Triggered { download_id } => Ok(download_id),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    pub async fn list_recurring(&self) -> Result<Vec<RecurringDownload>, ClientError> {
+        match self.request(Command::ListRecurring).await? {
+            Response::Recurring { recurring } => Ok(recurring),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Receive the changes of the downloads from now on.
+    ///
+    /// A receiver falling too far behind gets [`broadcast::error::RecvError::Lagged`],
+    /// and can catch up with [`Client::list`]. It is closed once the connection is.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.resubscribe()
+    }
+}
+
+/// i.e. "download-box:7878" -> "download-box", "[::1]:7878" -> "::1"
+fn host(address: &str) -> &str {
+    address
+        .rsplit_once(':')
+        .map_or(address, |(host, _)| host)
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+}
+
+/// Hand the replies to the requests waiting for them, and publish the events.
+async fn read_messages(
+    read_half: impl AsyncRead + Unpin,
+    pending: Pending,
+    events: broadcast::Sender<Event>,
+) {
+    let mut reader = BufReader::new(read_half);
+    let mut line = String::new();
+    // i.e. The server refused the connection, and closes it next.
+    let mut refusal = None;
+
+    loop {
+        match reader.read_line(&mut line).await {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to read from the server: {e}");
+                break;
+            }
+        }
+
+        let message = serde_json::from_str::<ServerMessage>(&line);
+        line.clear();
+
+        let (request_id, reply) = match message {
+            Ok(ServerMessage::Reply {
+                request_id,
+                response,
+            }) => (request_id, Ok(response)),
+            Ok(ServerMessage::Error {
+                request_id: Some(request_id),
+                code,
+                message,
+            }) => (request_id, Err(ClientError::Server { code, message })),
+            Ok(ServerMessage::Error {
+                request_id: None,
+                code,
+                message,
+            }) => {
+                warn!("Error from the server: {message}");
+                refusal = Some((code, message));
+                continue;
+            }
+            // Fails only if nobody is subscribed, nobody to tell.
+            Ok(ServerMessage::Download { download }) => {
+                let _ = events.send(Event::Download(download));
+                continue;
+            }
+            Ok(ServerMessage::Snapshot { downloads }) => {
+                let _ = events.send(Event::Snapshot(downloads));
+                continue;
+            }
+            Err(e) => {
+                error!("Deserialization Error: {e:#?}");
+                continue;
+            }
+        };
+
+        let reply_tx = pending
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|pending| pending.remove(&request_id));
+        match reply_tx {
+            // Fails only if the request is no longer awaited.
+            Some(reply_tx) => {
+                let _ = reply_tx.send(reply);
+            }
+            None => warn!("Reply to an unknown request {request_id}"),
+        }
+    }
+
+    // Requests still waiting get the last error of the server, or `Disconnected`.
+    let waiting = pending.lock().unwrap().take().unwrap_or_default();
+    for reply_tx in waiting.into_values() {
+        let error = match &refusal {
+            Some((code, message)) => ClientError::Server {
+                code: *code,
+                message: message.clone(),
+            },
+            None => ClientError::Disconnected,
+        };
+        let _ = reply_tx.send(Err(error));
+    }
+}
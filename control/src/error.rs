@@ -0,0 +1,46 @@
+use dlm_protocol::ErrorCode;
+use dlm_protocol::Response;
+use tokio_native_tls::native_tls;
+
+#[derive(Debug)]
+pub enum ClientError {
+    IoError(std::io::Error),
+    TlsError(native_tls::Error),
+    /// Server replied to the request with an error.
+    Server {
+        code: ErrorCode,
+        message: String,
+    },
+    /// Server replied with a response that does not belong to the command.
+    UnexpectedResponse(Box<Response>),
+    /// Connection closed before the reply arrived.
+    Disconnected,
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::IoError(e) => write!(f, "Io Error: {e}"),
+            ClientError::TlsError(e) => write!(f, "Tls Error: {e}"),
+            ClientError::Server { message, .. } => write!(f, "{message}"),
+            ClientError::UnexpectedResponse(response) => {
+                write!(f, "Unexpected response: {response:?}")
+            }
+            ClientError::Disconnected => write!(f, "Disconnected from the server"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(value: std::io::Error) -> Self {
+        ClientError::IoError(value)
+    }
+}
+
+impl From<native_tls::Error> for ClientError {
+    fn from(value: native_tls::Error) -> Self {
+        ClientError::TlsError(value)
+    }
+}
@@ -0,0 +1,46 @@
+//! Typed async client of `dlm_server`.
+//!
+//! [`Client`] sends the commands over the socket of the server and returns
+//! their replies. Changes of the downloads are received with [`Client::subscribe`].
+//! A server on another machine is reached with [`Client::connect_tcp`] or
+//! [`Client::connect_tls`], and [`Client::spawn`] runs one as a subprocess.
+//! Any other [`Transport`] goes through [`Client::connect_with`].
+//!
+//! ```no_run
+//! use dlm_control::{Client, ClientError, DownloadOptions, Event};
+//!
+//! # async fn run() -> Result<(), ClientError> {
+//! let client = Client::connect(dlm_control::socket_path()).await?;
+//! let mut events = client.subscribe();
+//!
+//! let urls = vec!["https://example.org/file.iso".to_string()];
+//! let (ids, rejected) = client.add(urls, DownloadOptions::default()).await?;
+//! for rejected in rejected {
+//!     eprintln!("Rejected {}: {}", rejected.url, rejected.message);
+//! }
+//!
+//! while let Ok(Event::Download(download)) = events.recv().await {
+//!     println!("{} {}% {}", download.id, download.progress, download.state);
+//! }
+//! client.cancel(ids[0], true).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod error;
+mod socket;
+
+pub use client::Client;
+pub use client::Event;
+pub use dlm_protocol::Command;
+pub use dlm_protocol::DownloadOptions;
+pub use dlm_protocol::DownloadSnapshot;
+pub use dlm_protocol::ErrorCode;
+pub use dlm_protocol::RecurringDownload;
+pub use dlm_protocol::Rejected;
+pub use dlm_protocol::Response;
+pub use dlm_protocol::State;
+pub use dlm_protocol::Transport;
+pub use error::ClientError;
+pub use socket::socket_path;
@@ -0,0 +1,16 @@
+use dlm_protocol::config_path;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Socket of the server, found the same way the server does: `$DLM_SOCKET`, the `socket`
+/// of its configuration file, or the default one. See [`dlm_protocol::socket_path`].
+pub fn socket_path() -> PathBuf {
+    // Only the socket is read, the rest of the configuration is the server's business.
+    let configured = config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|config| Some(PathBuf::from(config.get("socket")?.as_str()?)));
+
+    dlm_protocol::socket_path(configured)
+}
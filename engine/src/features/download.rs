@@ -0,0 +1,1581 @@
+use dlm_protocol::DownloadFailure;
+use dlm_protocol::DownloadOptions;
+use dlm_protocol::DownloadSnapshot;
+use dlm_protocol::State;
+use dlm_protocol::Verification;
+use dlm_protocol::VerificationStatus;
+use futures::Stream;
+use futures::StreamExt;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::ETAG;
+use reqwest::header::IF_MODIFIED_SINCE;
+use reqwest::header::IF_NONE_MATCH;
+use reqwest::header::IF_RANGE;
+use reqwest::header::LAST_MODIFIED;
+use reqwest::header::LOCATION;
+use reqwest::header::RANGE;
+use reqwest::header::REFERER;
+use reqwest::header::RETRY_AFTER;
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use reqwest::RequestBuilder;
+use reqwest::Response;
+use reqwest::StatusCode;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufWriter;
+use tokio::sync::broadcast;
+use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use url::Url;
+
+use std::collections::HashMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Redirects followed before giving up on the request.
+const MAX_REDIRECTS: usize = 10;
+/// Attempts made while the host keeps responding with `429`/`503`. Each waits as asked.
+const MAX_RATE_LIMITED_ATTEMPTS: usize = 5;
+/// Events kept for the subscribers. A subscriber falling further behind lags.
+const EVENTS_CAPACITY: usize = 1024;
+
+use super::host_limit::HostLimiter;
+use super::metadata;
+use super::options::OptionsExt;
+use super::verify::digests_from_headers;
+use super::verify::verify_file;
+use super::verify::ExpectedDigest;
+use crate::utils::filter_name;
+use crate::utils::private_address;
+use crate::utils::url_host;
+use crate::utils::validate_url;
+use crate::utils::Config;
+use crate::utils::DownloadError;
+use crate::utils::PublicResolver;
+use crate::utils::UrlPolicy;
+
+#[derive(Clone, Debug)]
+pub struct SingleDownload {
+    pub id: usize,
+    pub progress: usize,
+    url: String,
+    total_length: usize,
+    /// Bytes already written to the partial file. Used as the offset of the
+    /// `Range` request when a paused download is resumed.
+    downloaded: usize,
+    client: Client,
+    /// Directory the destination has to stay inside of.
+    download_dir: PathBuf,
+    destination: PathBuf,
+    notify: Arc<Notify>,
+    /// Held by the task that owns the open connection and file, so a resumed
+    /// transfer waits for the paused one to flush and exit.
+    transfer: Arc<Mutex<()>>,
+    /// Cancelled to abort the download task immediately, wherever it is awaiting.
+    cancel: CancellationToken,
+    /// When the download has to be completed by, if it has a deadline.
+    deadline: Option<Instant>,
+    state: State,
+    /// Run of the download, bumped whenever a new task takes over from the previous one.
+    generation: usize,
+    /// Number of attempts made by the current download run.
+    attempts: usize,
+    /// Last error, set while retrying and once the download has failed.
+    error: Option<DownloadFailure>,
+    options: DownloadOptions,
+    /// Urls the last request was redirected through, in order.
+    redirects: Vec<String>,
+    /// `ETag` of the resource, used to make sure a resumed download continues the same file.
+    etag: Option<String>,
+    /// `Last-Modified` of the resource.
+    last_modified: Option<String>,
+    /// Digests of the file sent by the server, checked once the download completes.
+    digests: Vec<ExpectedDigest>,
+    verification: Option<Verification>,
+    /// Redirects are checked against it as well.
+    policy: Arc<UrlPolicy>,
+    tx: broadcast::Sender<DownloadSnapshot>,
+}
+
+#[allow(dead_code)]
+impl SingleDownload {
+    pub fn new(
+        url: &str,
+        id: usize,
+        options: DownloadOptions,
+        download_dir: &Path,
+        policy: Arc<UrlPolicy>,
+        tx: broadcast::Sender<DownloadSnapshot>,
+    ) -> Result<Self, DownloadError> {
+        let destination = options
+            .destination
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(filter_name(url.to_string())));
+
+        Ok(SingleDownload {
+            id,
+            progress: 0,
+            url: url.to_string(),
+            total_length: 0,
+            downloaded: 0,
+            client: http_client(&options, &policy),
+            destination: inside(download_dir, &destination)?,
+            download_dir: download_dir.to_path_buf(),
+            notify: Arc::new(Notify::new()),
+            transfer: Arc::new(Mutex::new(())),
+            cancel: CancellationToken::new(),
+            deadline: options
+                .deadline
+                .map(|seconds| Instant::now() + Duration::from_secs(seconds)),
+            state: State::Pending,
+            generation: 0,
+            attempts: 0,
+            error: None,
+            options,
+            redirects: Vec::new(),
+            etag: None,
+            last_modified: None,
+            digests: Vec::new(),
+            verification: None,
+            policy,
+            tx,
+        })
+    }
+
+    /// The download as sent to the clients.
+    pub fn snapshot(&self) -> DownloadSnapshot {
+        DownloadSnapshot {
+            id: self.id,
+            progress: self.progress,
+            url: self.url.clone(),
+            total_length: self.total_length,
+            downloaded: self.downloaded,
+            destination: self.destination.clone(),
+            state: self.state.clone(),
+            attempts: self.attempts,
+            error: self.error.clone(),
+            options: self.options.clone(),
+            redirects: self.redirects.clone(),
+            etag: self.etag.clone(),
+            last_modified: self.last_modified.clone(),
+            verification: self.verification.clone(),
+        }
+    }
+
+    fn deadline_exceeded(&self) -> DownloadError {
+        DownloadError::Deadline(self.options.deadline.unwrap_or_default())
+    }
+
+    /// File the data is written to, until it is complete and renamed to `destination`.
+    fn part_path(&self) -> PathBuf {
+        let mut name = self
+            .destination
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        name.push(".part");
+        self.destination.with_file_name(name)
+    }
+
+    /// Create the directory of the destination.
+    ///
+    /// Fails if it is not inside the download directory once symlinks are resolved.
+    async fn create_parent(&self) -> Result<(), DownloadError> {
+        let Some(parent) = self.destination.parent() else {
+            return Ok(());
+        };
+        tokio::fs::create_dir_all(parent).await?;
+
+        let parent = tokio::fs::canonicalize(parent).await?;
+        let download_dir = tokio::fs::canonicalize(&self.download_dir).await?;
+        if !parent.starts_with(&download_dir) {
+            return Err(outside_download_dir());
+        }
+        Ok(())
+    }
+
+    /// Make the GET request, following and recording the redirects.
+    ///
+    /// Only a response with success status code is returned. In update mode that
+    /// includes `304 Not Modified`.
+    async fn send_request(&mut self, range: Option<String>) -> Result<Response, DownloadError> {
+        let mut url = Url::parse(&self.url).map_err(|e| DownloadError::Other(e.to_string()))?;
+        self.redirects.clear();
+
+        loop {
+            let mut http_request = self.client.get(url.clone());
+            if let Some(referrer) = &self.options.referrer {
+                http_request = http_request.header(REFERER, referrer);
+            }
+            if range.is_none() && self.options.update && self.destination.exists() {
+                http_request = self.conditional(http_request);
+            }
+            if let Some(range) = &range {
+                http_request = http_request.header(RANGE, range);
+                // Whole file is sent instead, if it changed in the meantime.
+                if let Some(etag) = &self.etag {
+                    http_request = http_request.header(IF_RANGE, etag);
+                }
+            }
+            let http_response = http_request
+                .send()
+                .await
+                .map_err(|e| request_error(e, self.options.idle_timeout))?;
+            let status = http_response.status();
+
+            if status == StatusCode::NOT_MODIFIED {
+                return Ok(http_response);
+            }
+
+            if status.is_redirection() {
+                let location = http_response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .ok_or(DownloadError::HttpStatus(status.as_u16()))?;
+
+                if self.redirects.len() >= MAX_REDIRECTS {
+                    return Err(DownloadError::Other("Too many redirects".to_string()));
+                }
+
+                url = url
+                    .join(location)
+                    .map_err(|e| DownloadError::Other(e.to_string()))?;
+                self.policy.check(url.as_str()).await?;
+                self.redirects.push(url.to_string());
+                continue;
+            }
+
+            if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+            {
+                return Err(DownloadError::RateLimited {
+                    status: status.as_u16(),
+                    retry_after: retry_after(&http_response),
+                });
+            }
+
+            if !status.is_success() {
+                return Err(DownloadError::HttpStatus(status.as_u16()));
+            }
+
+            return Ok(http_response);
+        }
+    }
+
+    /// Make the request conditional on the file being changed since the last download.
+    ///
+    /// Modification time of the file is used, if `Last-Modified` of it is not known.
+    fn conditional(&self, http_request: RequestBuilder) -> RequestBuilder {
+        let mut http_request = http_request;
+        if let Some(etag) = &self.etag {
+            http_request = http_request.header(IF_NONE_MATCH, etag);
+        }
+
+        let modified = self.last_modified.clone().or_else(|| {
+            std::fs::metadata(&self.destination)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(httpdate::fmt_http_date)
+        });
+        if let Some(modified) = modified {
+            http_request = http_request.header(IF_MODIFIED_SINCE, modified);
+        }
+
+        http_request
+    }
+
+    /// Size and `ETag` of the resource, requesting only its first byte.
+    async fn probe(&mut self) -> Result<(usize, Option<String>), DownloadError> {
+        let http_response = self.send_request(Some("bytes=0-0".to_string())).await?;
+
+        let size = if http_response.status() == StatusCode::PARTIAL_CONTENT {
+            content_range(&http_response)
+                .and_then(|(_, size)| size)
+                .unwrap_or(0)
+        } else {
+            http_response.content_length().unwrap_or(0) as usize
+        };
+
+        Ok((size, etag(&http_response)))
+    }
+}
+
+/// Downloads and the limits they share.
+///
+/// Clones are handles to the same downloads, so it can be cloned into every task using it.
+#[derive(Clone, Debug)]
+pub struct DownloadManager {
+    no_of_downloads: Arc<AtomicUsize>,
+    infos: Arc<Mutex<HashMap<usize, Arc<Mutex<SingleDownload>>>>>,
+    /// Every change of a download is published to the subscribers.
+    tx: broadcast::Sender<DownloadSnapshot>,
+    active_urls: Arc<Mutex<Vec<String>>>,
+    semaphore: Arc<Semaphore>,
+    hosts: Arc<HostLimiter>,
+    policy: Arc<UrlPolicy>,
+    config: Arc<Config>,
+}
+
+impl DownloadManager {
+    /// Create a manager with no downloads, using the limits and the policy of `config`.
+    pub fn new(config: Config) -> Self {
+        let (tx, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        DownloadManager {
+            no_of_downloads: Arc::new(AtomicUsize::new(0)),
+            infos: Arc::new(Mutex::new(HashMap::new())),
+            active_urls: Arc::new(Mutex::new(Vec::new())),
+            semaphore: Arc::new(Semaphore::new(10)),
+            hosts: Arc::new(HostLimiter::new(
+                config.host_connections,
+                config.host_requests_per_second,
+            )),
+            policy: Arc::new(config.policy.clone()),
+            config: Arc::new(config),
+            tx,
+        }
+    }
+
+    /// Add the downloads of the urls and start them in the background.
+    /// Returns the ids of the added ones.
+    ///
+    /// Invalid urls, and the ones already downloading, are skipped. The urls are not
+    /// checked against the policy, see [`DownloadManager::check_url`].
+    pub async fn enqueue(&self, urls: Vec<String>, options: DownloadOptions) -> Vec<usize> {
+        let ids = self.add_urls(urls, options).await;
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            this.download().await;
+        });
+
+        ids
+    }
+
+    /// Add the downloads of the urls, without starting them. Returns the ids of the added ones.
+    pub async fn add_urls(&self, urls: Vec<String>, options: DownloadOptions) -> Vec<usize> {
+        let options = options.with_defaults(&self.config);
+        let download_dir = self.config.download_dir();
+        let mut ids = Vec::new();
+        // Held until the downloads are added, so a url added twice at once gets a single one.
+        let mut infos = self.infos.lock().await;
+
+        for url in urls {
+            if let Err(e) = validate_url(&url) {
+                error!("Failed to validate the url:{url}.\nMore: {e:#?}");
+                continue;
+            }
+
+            // Taken once the download is added. Nothing else adds downloads meanwhile.
+            let id = self.no_of_downloads.load(Ordering::Relaxed) + 1;
+
+            let mut single_download = match SingleDownload::new(
+                &url,
+                id,
+                options.clone(),
+                &download_dir,
+                Arc::clone(&self.policy),
+                self.tx.clone(),
+            ) {
+                Ok(single_download) => single_download,
+                Err(e) => {
+                    error!("Failed to add the url:{url}.\nMore: {e}");
+                    continue;
+                }
+            };
+            // Both would write to the same file, i.e. a destination given for several urls.
+            let destination = &single_download.destination;
+            if any_unfinished(&infos, |info| {
+                info.url.trim() == url.trim() || info.destination == *destination
+            })
+            .await
+            {
+                warn!("URL is already downloading, or its destination is in use");
+                continue;
+            }
+
+            // Partial file of an earlier run, i.e. before the daemon restarted. It is continued
+            // if the server supports range requests, and started again otherwise.
+            single_download.downloaded = tokio::fs::metadata(single_download.part_path())
+                .await
+                .map_or(0, |metadata| metadata.len() as usize);
+            if options.update {
+                // Validators of the last download of the same url, to check it for changes.
+                let mut ids: Vec<&usize> = infos.keys().collect();
+                ids.sort();
+                for info in ids.into_iter().map(|id| &infos[id]) {
+                    let info = info.lock().await;
+                    if info.url.trim() == url.trim()
+                        && (info.state == State::Completed || info.state == State::UpToDate)
+                    {
+                        single_download.etag = info.etag.clone();
+                        single_download.last_modified = info.last_modified.clone();
+                    }
+                }
+            }
+
+            self.no_of_downloads.fetch_add(1, Ordering::Relaxed);
+            infos.insert(id, Arc::new(Mutex::new(single_download)));
+            ids.push(id);
+        }
+
+        ids
+    }
+
+    async fn entry(&self, id: usize) -> Result<Arc<Mutex<SingleDownload>>, DownloadError> {
+        self.infos
+            .lock()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| DownloadError::Other(format!("No download with id {id}")))
+    }
+
+    /// Every download, ordered by id.
+    async fn entries(&self) -> Vec<Arc<Mutex<SingleDownload>>> {
+        let infos = self.infos.lock().await;
+        let mut ids: Vec<&usize> = infos.keys().collect();
+        ids.sort();
+
+        ids.into_iter().map(|id| Arc::clone(&infos[id])).collect()
+    }
+
+    /// Check the url before it is submitted, against the policy of the configuration.
+    pub async fn check_url(&self, url: &str) -> Result<(), DownloadError> {
+        validate_url(url).map_err(|e| DownloadError::Other(e.to_string()))?;
+        self.policy.check(url).await
+    }
+
+    /// Check that the destination stays inside the download directory.
+    ///
+    /// Downloads with other destinations are not added, see [`DownloadManager::add_urls`].
+    pub fn check_destination(&self, destination: &Path) -> Result<(), DownloadError> {
+        inside(&self.config.download_dir(), destination).map(|_| ())
+    }
+
+    /// Pause the download with given id.
+    ///
+    /// The running task drops the http connection and flushes the file, so the
+    /// download can stay paused for as long as needed.
+    pub async fn pause_downloading(&self, id: usize) -> Result<(), DownloadError> {
+        let info = self.entry(id).await?;
+        let mut locked_info = info.lock().await;
+        // We can pause only if it is downloading.
+        if locked_info.state != State::Downloading {
+            return Err(not_in_state(&locked_info, "downloading"));
+        }
+
+        locked_info.state = State::Paused;
+        locked_info.notify.notify_one();
+        self.send_back_progress(locked_info).await;
+        Ok(())
+    }
+
+    /// Resume the paused download with given id.
+    ///
+    /// A new request is made with `Range` header starting from the bytes already on disk.
+    pub async fn resume_download(&self, id: usize) -> Result<(), DownloadError> {
+        let info = self.entry(id).await?;
+        let mut locked_info = info.lock().await;
+        // We can Resume only if it is paused.
+        if locked_info.state != State::Paused {
+            return Err(not_in_state(&locked_info, "paused"));
+        }
+
+        locked_info.state = State::Pending;
+        locked_info.generation += 1;
+        let generation = locked_info.generation;
+        let url = locked_info.url.trim().to_string();
+        self.send_back_progress(locked_info).await;
+
+        // The stopping task may still hold the url, so spawn regardless.
+        // Transfers of the same download never overlap.
+        self.mark_active(url).await;
+        self.spawn_download(info, generation);
+        Ok(())
+    }
+
+    /// Retry the failed or canceled download with given id.
+    ///
+    /// Reuses the existing entry and continues from the partially downloaded file if possible.
+    pub async fn retry_download(&self, id: usize) -> Result<(), DownloadError> {
+        let info = self.entry(id).await?;
+        let mut locked_info = info.lock().await;
+        if locked_info.state != State::Failed && locked_info.state != State::Canceled {
+            return Err(not_in_state(&locked_info, "failed or canceled"));
+        }
+
+        locked_info.state = State::Pending;
+        locked_info.attempts = 0;
+        locked_info.error = None;
+        // Token of the canceled download can not be reused.
+        locked_info.cancel = CancellationToken::new();
+        // Retried download gets the whole deadline again.
+        locked_info.deadline = locked_info
+            .options
+            .deadline
+            .map(|seconds| Instant::now() + Duration::from_secs(seconds));
+        locked_info.generation += 1;
+        let generation = locked_info.generation;
+        let url = locked_info.url.trim().to_string();
+        self.send_back_progress(locked_info).await;
+
+        // The stopping task may still hold the url, so spawn regardless.
+        // Transfers of the same download never overlap.
+        self.mark_active(url).await;
+        self.spawn_download(info, generation);
+        Ok(())
+    }
+
+    /// Replace the url of the download with given id, i.e. when a signed url expires.
+    ///
+    /// The new url has to serve the same file, compared by size and `ETag` when known.
+    /// Download continues from the current offset using the new url.
+    pub async fn update_url(&self, id: usize, url: String) -> Result<(), DownloadError> {
+        self.check_url(&url).await?;
+
+        let single_info = self.entry(id).await?;
+
+        let mut probe = single_info.lock().await.clone();
+        if probe.state == State::Completed {
+            return Err(DownloadError::Other(
+                "Download is already completed".to_string(),
+            ));
+        }
+
+        probe.url = url.clone();
+        let (size, new_etag) = probe.probe().await?;
+
+        let mut locked_info = single_info.lock().await;
+        if locked_info.total_length != 0 && size != 0 && locked_info.total_length != size {
+            return Err(DownloadError::Other(format!(
+                "Size of the new url differs: {size} instead of {}",
+                locked_info.total_length
+            )));
+        }
+        if let (Some(etag), Some(new_etag)) = (&locked_info.etag, &new_etag) {
+            if etag.trim_start_matches("W/") != new_etag.trim_start_matches("W/") {
+                return Err(DownloadError::Other(format!(
+                    "ETag of the new url differs: {new_etag} instead of {etag}"
+                )));
+            }
+        }
+
+        let old_url = std::mem::replace(&mut locked_info.url, url.trim().to_string());
+        {
+            let mut active_urls = self.active_urls.lock().await;
+            for active in active_urls.iter_mut() {
+                if *active == old_url.trim() {
+                    *active = locked_info.url.clone();
+                }
+            }
+        }
+        info!("Url of {id} is updated");
+
+        // Pending and Retrying downloads pick up the new url on their next request.
+        // Running and failed ones are restarted from the current offset.
+        match locked_info.state {
+            State::Downloading | State::Failed => {
+                locked_info.state = State::Pending;
+                locked_info.attempts = 0;
+                locked_info.error = None;
+                locked_info.cancel = CancellationToken::new();
+                locked_info.generation += 1;
+                let generation = locked_info.generation;
+                // Running transfer stops and the new one continues after it.
+                locked_info.notify.notify_one();
+
+                let url = locked_info.url.clone();
+                self.send_back_progress(locked_info).await;
+                self.mark_active(url).await;
+                self.spawn_download(Arc::clone(&single_info), generation);
+            }
+            _ => self.send_back_progress(locked_info).await,
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the download with given id.
+    ///
+    /// The download task is aborted right away, releasing its permit and closing the file.
+    /// If `delete` is true the partially downloaded file is removed as well.
+    /// Finished downloads can not be canceled.
+    pub async fn cancel_downloading(&self, id: usize, delete: bool) -> Result<(), DownloadError> {
+        let info = self.entry(id).await?;
+        let mut locked_info = info.lock().await;
+        // i.e. Canceling a completed download would make it downloaded again on retry.
+        if locked_info.state.is_finished() {
+            return Err(not_in_state(&locked_info, "in progress"));
+        }
+
+        let paused = locked_info.state == State::Paused;
+        let part_path = locked_info.part_path();
+        let transfer = Arc::clone(&locked_info.transfer);
+        let url = locked_info.url.trim().to_string();
+
+        locked_info.state = State::Canceled;
+        locked_info.cancel.cancel();
+        self.send_back_progress(locked_info).await;
+
+        // No task is left to release the url of a paused download.
+        if paused {
+            self.active_urls
+                .lock()
+                .await
+                .retain(|active| *active != url);
+        }
+
+        if delete {
+            // Wait for the aborted task to drop the file.
+            let _transfer = transfer.lock().await;
+            if let Err(e) = tokio::fs::remove_file(&part_path).await {
+                warn!("Failed to remove the partial file {part_path:?}: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Current state of every download, ordered by id.
+    pub async fn list_downloads(&self) -> Vec<DownloadSnapshot> {
+        let mut vec = Vec::new();
+        for info in self.entries().await {
+            let locked_info = info.lock().await;
+            vec.push(locked_info.snapshot());
+        }
+
+        vec
+    }
+
+    /// Receive the changes of every download from now on.
+    ///
+    /// A receiver falling too far behind gets [`broadcast::error::RecvError::Lagged`],
+    /// and can catch up with [`DownloadManager::list_downloads`].
+    pub fn subscribe(&self) -> broadcast::Receiver<DownloadSnapshot> {
+        self.tx.subscribe()
+    }
+
+    /// This send the current progress info i.e. [SingleDownload::snapshot] to the clients
+    ///
+    /// info that is locked and passed to the function is droped.
+    async fn send_back_progress(&self, info: tokio::sync::MutexGuard<'_, SingleDownload>) {
+        // Fails only if no client is connected, nobody to tell.
+        let _ = info.tx.send(info.snapshot());
+        drop(info);
+    }
+
+    /// Set the modification time and the origin of the completed file, as configured.
+    fn set_metadata(&self, info: &SingleDownload) {
+        if self.config.set_mtime {
+            if let Some(last_modified) = &info.last_modified {
+                metadata::set_mtime(&info.destination, last_modified);
+            }
+        }
+
+        if self.config.set_xattrs {
+            let url = info.redirects.last().unwrap_or(&info.url);
+            metadata::set_origin(&info.destination, url, info.options.referrer.as_deref());
+        }
+    }
+
+    #[inline]
+    /// Make http request and download the data
+    ///
+    /// Continues from `downloaded` bytes if the server supports range requests.
+    async fn single_download(
+        &mut self,
+        single_info: Arc<Mutex<SingleDownload>>,
+    ) -> Result<(), DownloadError> {
+        let transfer = Arc::clone(&single_info.lock().await.transfer);
+        let _transfer = transfer.lock().await;
+
+        let host = url_host(&single_info.lock().await.url);
+        self.hosts.request(&host).await;
+
+        let mut info = single_info.lock().await;
+        if ![State::Pending, State::Downloading, State::Retrying].contains(&info.state) {
+            return Ok(());
+        }
+
+        let deadline = info.deadline;
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            return Err(info.deadline_exceeded());
+        }
+
+        // Partial file could have been removed or truncated in the meantime.
+        let on_disk = tokio::fs::metadata(info.part_path())
+            .await
+            .map_or(0, |metadata| metadata.len() as usize);
+        info.downloaded = info.downloaded.min(on_disk);
+
+        // Request is made on a copy, so the download can be paused or listed meanwhile.
+        let generation = info.generation;
+        let mut request = info.clone();
+        drop(info);
+
+        let http_response = loop {
+            let range = (request.downloaded != 0).then(|| format!("bytes={}-", request.downloaded));
+            let response = tokio::select! {
+                response = request.send_request(range) => response,
+                _ = deadline_reached(deadline) => Err(request.deadline_exceeded()),
+            };
+            let http_response = match response {
+                Ok(http_response) => {
+                    self.hosts.succeeded(&host);
+                    http_response
+                }
+                Err(e) => {
+                    if let DownloadError::RateLimited { retry_after, .. } = e {
+                        self.hosts.throttled(&host, retry_after);
+                    }
+                    return Err(e);
+                }
+            };
+
+            // Appending any other part of the file would corrupt it.
+            let start = content_range(&http_response).map(|(start, _)| start);
+            if http_response.status() == StatusCode::PARTIAL_CONTENT
+                && start != Some(request.downloaded)
+            {
+                if request.downloaded == 0 {
+                    return Err(DownloadError::Other(format!(
+                        "Unexpected partial response starting at {start:?}"
+                    )));
+                }
+
+                warn!(
+                    "{} sent bytes from {start:?} instead of {}; starting again",
+                    request.url, request.downloaded
+                );
+                request.downloaded = 0;
+                continue;
+            }
+
+            break http_response;
+        };
+
+        let mut info = single_info.lock().await;
+        // Paused, canceled or taken over by another task while the request was made.
+        if info.generation != generation
+            || ![State::Pending, State::Downloading, State::Retrying].contains(&info.state)
+        {
+            return Ok(());
+        }
+        info.downloaded = request.downloaded;
+        info.redirects = request.redirects;
+
+        // File on disk is the same as the remote one. It is left untouched.
+        if http_response.status() == StatusCode::NOT_MODIFIED {
+            info!("{} is up to date", info.url);
+            info.state = State::UpToDate;
+            info.progress = 100;
+            self.send_back_progress(info).await;
+            return Ok(());
+        }
+
+        // Server ignored the range request. Start again from the beginning.
+        if http_response.status() != StatusCode::PARTIAL_CONTENT {
+            info.downloaded = 0;
+        }
+        let mut downloaded = info.downloaded;
+        info.etag = etag(&http_response).or(info.etag.take());
+        info.last_modified = http_response
+            .headers()
+            .get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+            .or(info.last_modified.take());
+
+        info.total_length = http_response
+            .content_length()
+            .map_or(0, |length| downloaded + length as usize);
+
+        // Nothing is written to the file unless the response is the expected one.
+        info.options.check_size(info.total_length)?;
+        info.options.check_content_type(
+            http_response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok()),
+        )?;
+
+        // Partial response only carries the digests of the whole file, if any.
+        let digests = digests_from_headers(http_response.headers(), downloaded == 0);
+        if downloaded == 0 || !digests.is_empty() {
+            info.digests = digests;
+        }
+
+        info.state = State::Downloading;
+
+        let mut stream = http_response.bytes_stream();
+        info.create_parent().await?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(info.part_path())
+            .await?;
+        // Discard anything past the offset that was requested.
+        file.set_len(downloaded as u64).await?;
+        let mut file = BufWriter::with_capacity(1024 * 1024, file);
+
+        let notify = Arc::clone(&info.notify);
+        let idle_timeout = info.options.idle_timeout.map(Duration::from_secs);
+        let mut low_speed = info.options.low_speed_limit.map(|limit| {
+            LowSpeed::new(
+                limit,
+                Duration::from_secs(info.options.low_speed_time.unwrap_or(0)),
+            )
+        });
+        let options = info.options.clone();
+        let total_length = info.total_length;
+        drop(info);
+
+        loop {
+            let window_end = low_speed.as_ref().map(LowSpeed::window_end);
+            let chunk = tokio::select! {
+                chunk = next_chunk(&mut stream, idle_timeout) => match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        // Keep what was downloaded so far, so retry can continue from it.
+                        file.flush().await?;
+                        return Err(e);
+                    }
+                },
+                _ = deadline_reached(deadline) => {
+                    file.flush().await?;
+                    return Err(single_info.lock().await.deadline_exceeded());
+                }
+                // Checked without any chunk too, i.e. a transfer that stopped altogether.
+                _ = deadline_reached(window_end) => {
+                    let low_speed = low_speed.as_mut().expect("Window ends only with a limit");
+                    if let Err(e) = low_speed.received(0, Instant::now()) {
+                        file.flush().await?;
+                        return Err(e);
+                    }
+                    continue;
+                }
+                _ = notify.notified() => {
+                    let info = single_info.lock().await;
+                    if info.state == State::Downloading {
+                        continue;
+                    }
+
+                    // Paused, or restarted with a new url. Connection is dropped along with the stream.
+                    info!("Downloading stopped; {:?} at {} bytes", info.id, downloaded);
+                    drop(info);
+                    file.flush().await?;
+                    return Ok(());
+                }
+            };
+
+            let Some(chunk) = chunk else {
+                break;
+            };
+
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                // Connection closed before the announced length, i.e. the server went away.
+                Err(e) if total_length != 0 && !e.is_timeout() => {
+                    file.flush().await?;
+                    return Err(DownloadError::Truncated {
+                        expected: total_length,
+                        received: downloaded,
+                    });
+                }
+                Err(e) => {
+                    // Keep what was downloaded so far, so retry can continue from it.
+                    file.flush().await?;
+                    return Err(request_error(e, options.idle_timeout));
+                }
+            };
+            // Server could send more than it announced, or not announce the size at all.
+            if let Err(e) = options.check_max_size(downloaded + chunk.len()) {
+                file.flush().await?;
+                return Err(e);
+            }
+            file.write_all(&chunk).await?;
+            downloaded += chunk.len();
+
+            if let Some(low_speed) = &mut low_speed {
+                if let Err(e) = low_speed.received(chunk.len(), Instant::now()) {
+                    file.flush().await?;
+                    return Err(e);
+                }
+            }
+
+            // To Send the realtime progress.
+            let mut info = single_info.lock().await;
+            info.downloaded = downloaded;
+            // Calculating the percentage of the progress
+            info.progress = (downloaded * 100)
+                .checked_div(info.total_length)
+                .unwrap_or(0);
+            self.send_back_progress(info).await;
+        }
+
+        file.flush().await?;
+        drop(file);
+
+        let info = single_info.lock().await;
+        // Stream ended without an error but early. Retry continues from here.
+        if info.total_length != 0 && downloaded < info.total_length {
+            return Err(DownloadError::Truncated {
+                expected: info.total_length,
+                received: downloaded,
+            });
+        }
+        if info.state != State::Downloading {
+            return Ok(());
+        }
+
+        // Hashing a large file takes a while, the download is not locked meanwhile.
+        let part_path = info.part_path();
+        let digests = info.digests.clone();
+        drop(info);
+        let verification = verify_file(&part_path, &digests).await?;
+
+        let mut info = single_info.lock().await;
+        // File is complete, so a pause in the meantime does not stop it from being moved.
+        if info.generation == generation && info.state != State::Canceled {
+            info.verification = verification;
+
+            if let Some(verification) = &info.verification {
+                if verification.status == VerificationStatus::Mismatch {
+                    let error = DownloadError::Integrity(format!(
+                        "{:?} from {}",
+                        verification.algorithm, verification.header
+                    ));
+                    // File can not be trusted. Retry has to start from the beginning.
+                    info.downloaded = 0;
+                    return Err(error);
+                }
+            }
+
+            // Replaces the previous file at once, if there is one.
+            tokio::fs::rename(info.part_path(), &info.destination).await?;
+            self.set_metadata(&info);
+
+            // After completion of downloading.
+            info.state = State::Completed;
+            info.error = None;
+            self.send_back_progress(info).await;
+        }
+
+        Ok(())
+    }
+
+    /// Retry downloading if error occurs.
+    ///
+    /// Retry upto 2 times, or upto [`MAX_RATE_LIMITED_ATTEMPTS`] if the host is rate limiting.
+    /// The state is [`State::Retrying`] in between the attempts and [`State::Failed`] once all
+    /// of them failed or the error is not retryable.
+    #[inline]
+    async fn attempt_download(
+        &mut self,
+        single_info: Arc<Mutex<SingleDownload>>,
+    ) -> Result<(), DownloadError> {
+        let max_attempts = 2;
+
+        loop {
+            let error = match self.single_download(Arc::clone(&single_info)).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+
+            let mut info = single_info.lock().await;
+            info.attempts += 1;
+            warn!("\t__Try number: {}__\t", info.attempts);
+            info.error = Some(error.failure(info.attempts));
+
+            // Next request waits for the host, see `HostLimiter::throttled`.
+            let max_attempts = match error {
+                DownloadError::RateLimited { .. } => MAX_RATE_LIMITED_ATTEMPTS,
+                _ => max_attempts,
+            };
+            if info.attempts >= max_attempts || !error.is_retryable() {
+                info.state = State::Failed;
+                self.send_back_progress(info).await;
+                return Err(error);
+            }
+
+            info.state = State::Retrying;
+            self.send_back_progress(info).await;
+            tokio::time::sleep(Duration::from_millis(40)).await;
+        }
+    }
+
+    /// Mark the url as active. Returns false if it already was.
+    async fn mark_active(&self, url: String) -> bool {
+        let mut active_urls = self.active_urls.lock().await;
+        if active_urls.contains(&url) {
+            return false;
+        }
+
+        active_urls.push(url);
+        true
+    }
+
+    /// Spawn the download task unless the url already has one scheduled or running.
+    async fn schedule_download(
+        &self,
+        single_info: Arc<Mutex<SingleDownload>>,
+    ) -> Option<JoinHandle<()>> {
+        let (url, generation) = {
+            let info = single_info.lock().await;
+            (info.url.trim().to_string(), info.generation)
+        };
+
+        if !self.mark_active(url).await {
+            warn!("URL already present");
+            return None;
+        }
+
+        Some(self.spawn_download(single_info, generation))
+    }
+
+    /// Spawn the download task once a connection to the host and a permit are available.
+    ///
+    /// The task stops as soon as the download's cancellation token is cancelled.
+    /// Url of the download is no longer active once the task ends with the download finished,
+    /// unless another task took over the download (`generation` changed) in the meantime.
+    fn spawn_download(
+        &self,
+        single_info: Arc<Mutex<SingleDownload>>,
+        generation: usize,
+    ) -> JoinHandle<()> {
+        let semaphore = Arc::clone(&self.semaphore);
+        let hosts = Arc::clone(&self.hosts);
+        let mut this = self.clone();
+
+        tokio::spawn(async move {
+            let cancel = single_info.lock().await.cancel.clone();
+
+            tokio::select! {
+                _ = cancel.cancelled() => {
+                    info!("Downloading canceled; {:?}", single_info.lock().await.id);
+                }
+                _ = async {
+                    // Waiting for the host first keeps the permits for the other hosts.
+                    let host = url_host(&single_info.lock().await.url);
+                    let connection = hosts.connection(&host).await;
+                    let permit = semaphore.acquire_owned().await.unwrap();
+
+                    if let Err(err) = this.attempt_download(Arc::clone(&single_info)).await {
+                        error!("Failed to download the request.\nMore: {err:#?}");
+                    }
+
+                    drop(permit);
+                    drop(connection);
+                } => {}
+            }
+
+            // Paused download keeps its url, the file is still being written by it.
+            let info = single_info.lock().await;
+            if info.generation == generation && info.state.is_finished() {
+                let url = info.url.trim().to_string();
+                this.active_urls
+                    .lock()
+                    .await
+                    .retain(|active| *active != url);
+            }
+        })
+    }
+
+    /// Public Download Function
+    ///
+    /// async download the data from list of urls that are not started yet.
+    /// Returns once all of them are done.
+    pub async fn download(&self) {
+        let mut tasks = Vec::new();
+
+        for single_info in self.entries().await {
+            if single_info.lock().await.state != State::Pending {
+                continue;
+            }
+
+            if let Some(task) = self.schedule_download(single_info).await {
+                tasks.push(task);
+            }
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+}
+
+/// Path of the destination inside the download directory.
+///
+/// Fails if the destination is absolute, or has any `..` i.e. `../../.bashrc`.
+fn inside(download_dir: &Path, destination: &Path) -> Result<PathBuf, DownloadError> {
+    let escapes = destination
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes || destination.file_name().is_none() {
+        return Err(outside_download_dir());
+    }
+
+    Ok(download_dir.join(destination))
+}
+
+fn outside_download_dir() -> DownloadError {
+    DownloadError::Policy("Destination has to be inside the download directory".to_string())
+}
+
+/// Whether any of the downloads that are not finished yet matches.
+async fn any_unfinished(
+    infos: &HashMap<usize, Arc<Mutex<SingleDownload>>>,
+    matches: impl Fn(&SingleDownload) -> bool,
+) -> bool {
+    for info in infos.values() {
+        let info = info.lock().await;
+        if !info.state.is_finished() && matches(&info) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Command can not be carried out in the current state of the download.
+fn not_in_state(info: &SingleDownload, expected: &str) -> DownloadError {
+    DownloadError::Other(format!(
+        "Download {} is {:?}, not {expected}",
+        info.id, info.state
+    ))
+}
+
+/// Client making the requests of a download.
+///
+/// Connecting, and every read of the response, fails after `idle_timeout` too.
+fn http_client(options: &DownloadOptions, policy: &UrlPolicy) -> Client {
+    // Redirects are followed manually to record them.
+    let mut builder = Client::builder().redirect(Policy::none());
+    if policy.block_private {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    if let Some(idle_timeout) = options.idle_timeout.map(Duration::from_secs) {
+        builder = builder
+            .connect_timeout(idle_timeout)
+            .read_timeout(idle_timeout);
+    }
+
+    builder.build().expect("Could not build the http client")
+}
+
+/// Error of a request, a timeout of it counts as stalled.
+fn request_error(e: reqwest::Error, idle_timeout: Option<u64>) -> DownloadError {
+    if let Some(message) = private_address(&e) {
+        return DownloadError::Policy(message);
+    }
+
+    match idle_timeout {
+        Some(seconds) if e.is_timeout() => {
+            DownloadError::Stalled(format!("no data received for {seconds}s"))
+        }
+        _ => e.into(),
+    }
+}
+
+/// Transfer rate over a window of `time`, i.e. under 1024 bytes/s for 30s.
+struct LowSpeed {
+    /// Bytes per second.
+    limit: usize,
+    time: Duration,
+    /// Start of the current window and the bytes received since.
+    start: Instant,
+    bytes: usize,
+}
+
+impl LowSpeed {
+    fn new(limit: usize, time: Duration) -> Self {
+        LowSpeed {
+            limit,
+            // Speed over a shorter window is mostly noise.
+            time: time.max(Duration::from_secs(1)),
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /// When the current window is over, and the speed has to be checked.
+    fn window_end(&self) -> Instant {
+        self.start + self.time
+    }
+
+    /// Count the received bytes. Fails as stalled once a whole window was under the limit.
+    fn received(&mut self, bytes: usize, now: Instant) -> Result<(), DownloadError> {
+        self.bytes += bytes;
+
+        let elapsed = now.duration_since(self.start);
+        if elapsed < self.time {
+            return Ok(());
+        }
+
+        if (self.bytes as f64 / elapsed.as_secs_f64()) < self.limit as f64 {
+            return Err(DownloadError::Stalled(format!(
+                "under {} bytes/s for {}s",
+                self.limit,
+                elapsed.as_secs()
+            )));
+        }
+
+        self.start = now;
+        self.bytes = 0;
+        Ok(())
+    }
+}
+
+/// Next chunk of the response body.
+///
+/// Fails as stalled if nothing arrives within `idle_timeout`.
+async fn next_chunk<S: Stream + Unpin>(
+    stream: &mut S,
+    idle_timeout: Option<Duration>,
+) -> Result<Option<S::Item>, DownloadError> {
+    let Some(idle_timeout) = idle_timeout else {
+        return Ok(stream.next().await);
+    };
+
+    tokio::time::timeout(idle_timeout, stream.next())
+        .await
+        .map_err(|_| {
+            DownloadError::Stalled(format!("no data received for {}s", idle_timeout.as_secs()))
+        })
+}
+
+/// Completes once the deadline is reached. Never, if there is none.
+async fn deadline_reached(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Delay asked by the `Retry-After` header, given either in seconds or as a date.
+fn retry_after(http_response: &Response) -> Option<Duration> {
+    let value = http_response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+
+    match value.trim().parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(std::time::SystemTime::now())
+            .ok(),
+    }
+}
+
+/// First byte and complete length of a partial response, i.e. `bytes 100-199/1000`.
+///
+/// Length is None if it is unknown (`*`).
+fn content_range(http_response: &Response) -> Option<(usize, Option<usize>)> {
+    let value = http_response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, size) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+
+    Some((start.trim().parse().ok()?, size.trim().parse().ok()))
+}
+
+/// `ETag` header of the response.
+fn etag(http_response: &Response) -> Option<String> {
+    http_response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use dlm_protocol::FailureKind;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    /// Answer each connection with the next response, and keep it open for the duration after.
+    /// Responses closed right away say so with `Connection: close`, or the client reuses them.
+    /// Returns the address, and the heads of the requests received so far.
+    async fn serve(
+        responses: Vec<(&'static str, Duration)>,
+    ) -> (SocketAddr, Arc<std::sync::Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let received = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for (response, open) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let received = Arc::clone(&received);
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        head.push(stream.read_u8().await.unwrap());
+                    }
+                    received
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8(head).unwrap().to_lowercase());
+
+                    // Fails if the client gave up already.
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    tokio::time::sleep(open).await;
+                });
+            }
+        });
+
+        (address, requests)
+    }
+
+    /// Download `file` from the server into `download_dir`, until the download is over.
+    async fn download(
+        address: SocketAddr,
+        download_dir: &Path,
+        file: &str,
+        options: DownloadOptions,
+    ) -> DownloadSnapshot {
+        let manager = DownloadManager::new(Config {
+            idle_timeout: None,
+            download_dir: Some(download_dir.to_path_buf()),
+            ..Default::default()
+        });
+        let options = DownloadOptions {
+            destination: Some(PathBuf::from(file)),
+            ..options
+        };
+
+        let ids = manager
+            .add_urls(vec![format!("http://{address}/{file}")], options)
+            .await;
+        manager.download().await;
+
+        manager
+            .list_downloads()
+            .await
+            .into_iter()
+            .find(|download| download.id == ids[0])
+            .unwrap()
+    }
+
+    fn download_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dlm_engine_{}_{test}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_between_chunks() {
+        let mut body = stream::pending::<u8>();
+        let chunk = next_chunk(&mut body, Some(Duration::from_millis(50))).await;
+        assert!(matches!(chunk, Err(DownloadError::Stalled(_))));
+
+        let mut body = stream::iter([1, 2]);
+        let chunk = next_chunk(&mut body, Some(Duration::from_millis(50))).await;
+        assert_eq!(chunk.unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_before_the_response() {
+        // Accepts the connection, but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            drop(stream);
+        });
+
+        let options = DownloadOptions {
+            idle_timeout: Some(1),
+            ..Default::default()
+        };
+        let (tx, _) = broadcast::channel(1);
+        let mut download = SingleDownload::new(
+            &format!("http://{address}/file"),
+            1,
+            options,
+            &std::env::temp_dir(),
+            Arc::new(UrlPolicy::default()),
+            tx,
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let response = download.send_request(None).await;
+        server.abort();
+
+        assert!(matches!(response, Err(DownloadError::Stalled(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn low_speed_without_any_data() {
+        // Sends a part of the file, then nothing.
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n0123456789";
+        let (address, _) = serve(vec![(response, Duration::from_secs(10)); 2]).await;
+
+        let options = DownloadOptions {
+            low_speed_limit: Some(1000),
+            low_speed_time: Some(1),
+            ..Default::default()
+        };
+        let download_dir = download_dir("low_speed");
+        let started = Instant::now();
+        let download = download(address, &download_dir, "file", options).await;
+        tokio::fs::remove_dir_all(&download_dir).await.unwrap();
+
+        assert_eq!(download.state, State::Failed);
+        assert_eq!(download.error.unwrap().kind, FailureKind::Stalled);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn body_shorter_than_its_length() {
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n01234";
+        let (address, _) = serve(vec![(response, Duration::ZERO); 2]).await;
+
+        let download_dir = download_dir("truncated");
+        let download = download(address, &download_dir, "file", Default::default()).await;
+        tokio::fs::remove_dir_all(&download_dir).await.unwrap();
+
+        assert_eq!(download.state, State::Failed);
+        assert_eq!(download.error.unwrap().kind, FailureKind::Truncated);
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let response = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (address, _) = serve(vec![(response, Duration::ZERO)]).await;
+
+        let download_dir = download_dir("not_found");
+        let download = download(address, &download_dir, "file", Default::default()).await;
+        let _ = tokio::fs::remove_dir_all(&download_dir).await;
+
+        assert_eq!(download.state, State::Failed);
+        let error = download.error.unwrap();
+        assert_eq!(error.kind, FailureKind::Http);
+        assert_eq!(error.status, Some(404));
+        // Not worth retrying.
+        assert_eq!(error.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn partial_response_at_another_offset() {
+        // Sends the file from the start when asked to continue it, then the whole file.
+        let partial =
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-9/10\r\nContent-Length: 10\r\nConnection: close\r\n\r\n0123456789";
+        let whole = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nabcdefghij";
+        let (address, requests) =
+            serve(vec![(partial, Duration::ZERO), (whole, Duration::ZERO)]).await;
+
+        let download_dir = download_dir("offset");
+        tokio::fs::create_dir_all(&download_dir).await.unwrap();
+        tokio::fs::write(download_dir.join("file.part"), "01234")
+            .await
+            .unwrap();
+
+        let download = download(address, &download_dir, "file", Default::default()).await;
+        let content = tokio::fs::read(download_dir.join("file")).await.unwrap();
+        tokio::fs::remove_dir_all(&download_dir).await.unwrap();
+
+        assert_eq!(download.state, State::Completed);
+        assert_eq!(content, b"abcdefghij");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("range: bytes=5-"));
+        assert!(!requests[1].contains("range:"));
+    }
+
+    #[test]
+    fn low_speed() {
+        let mut low_speed = LowSpeed::new(100, Duration::from_secs(10));
+        let start = low_speed.start;
+
+        // Window is not over yet.
+        assert!(low_speed
+            .received(10, start + Duration::from_secs(5))
+            .is_ok());
+        // 1010 bytes in 10s. Next window starts.
+        assert!(low_speed
+            .received(1000, start + Duration::from_secs(10))
+            .is_ok());
+        assert!(low_speed
+            .received(0, start + Duration::from_secs(19))
+            .is_ok());
+        // 900 bytes in 10s.
+        assert!(matches!(
+            low_speed.received(900, start + Duration::from_secs(20)),
+            Err(DownloadError::Stalled(_))
+        ));
+    }
+
+    #[test]
+    fn destination_inside_the_download_dir() {
+        let download_dir = Path::new("/downloads");
+
+        for (destination, expected) in [
+            ("file.iso", Some("/downloads/file.iso")),
+            ("./isos/file.iso", Some("/downloads/isos/file.iso")),
+            ("/etc/passwd", None),
+            ("../file.iso", None),
+            ("isos/../../file.iso", None),
+            ("", None),
+            (".", None),
+        ] {
+            let path = inside(download_dir, Path::new(destination)).ok();
+            assert_eq!(path.as_deref(), expected.map(Path::new), "{destination}");
+        }
+    }
+
+    #[tokio::test]
+    async fn destination_through_a_symlink() {
+        let root = std::env::temp_dir().join(format!("dlm_symlink_{}", std::process::id()));
+        let download_dir = root.join("downloads");
+        tokio::fs::create_dir_all(&download_dir).await.unwrap();
+        tokio::fs::symlink(&root, download_dir.join("out"))
+            .await
+            .unwrap();
+        tokio::fs::create_dir_all(download_dir.join("isos"))
+            .await
+            .unwrap();
+
+        let (tx, _) = broadcast::channel(1);
+        let download = |destination: &str| {
+            let options = DownloadOptions {
+                destination: Some(PathBuf::from(destination)),
+                ..Default::default()
+            };
+            SingleDownload::new(
+                "http://example.org/file",
+                1,
+                options,
+                &download_dir,
+                Arc::new(UrlPolicy::default()),
+                tx.clone(),
+            )
+            .unwrap()
+        };
+
+        let escaped = download("out/file").create_parent().await;
+        let inside = download("isos/new/file").create_parent().await;
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+
+        assert!(matches!(escaped, Err(DownloadError::Policy(_))));
+        assert!(inside.is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Delay added after the first `429`/`503` without `Retry-After`. Doubles on each one after.
+const BASE_PENALTY: Duration = Duration::from_secs(1);
+const MAX_PENALTY: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Host {
+    connections: Arc<Semaphore>,
+    /// Earliest time the next request to the host can be made.
+    next_request: Instant,
+    /// Extra delay between requests after the host asked to slow down.
+    penalty: Duration,
+}
+
+impl Host {
+    /// Nothing holds or waits for a connection, and the host has no delay pending.
+    fn idle(&self, now: Instant) -> bool {
+        Arc::strong_count(&self.connections) == 1
+            && self.next_request <= now
+            && self.penalty.is_zero()
+    }
+}
+
+/// Limits the concurrent connections and request rate per host.
+#[derive(Debug)]
+pub struct HostLimiter {
+    max_connections: usize,
+    interval: Duration,
+    hosts: Mutex<HashMap<String, Host>>,
+}
+
+impl HostLimiter {
+    pub fn new(max_connections: usize, requests_per_second: Option<f64>) -> Self {
+        Self {
+            max_connections: max_connections.max(1),
+            interval: requests_per_second
+                .filter(|rate| *rate > 0.0)
+                .map_or(Duration::ZERO, |rate| Duration::from_secs_f64(1.0 / rate)),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `f` with the state of the host. Idle hosts are removed meanwhile.
+    fn with_host<T>(&self, host: &str, f: impl FnOnce(&mut Host) -> T) -> T {
+        let mut hosts = self.hosts.lock().unwrap();
+        let now = Instant::now();
+        hosts.retain(|name, state| name == host || !state.idle(now));
+
+        let host = hosts.entry(host.to_string()).or_insert_with(|| Host {
+            connections: Arc::new(Semaphore::new(self.max_connections)),
+            next_request: Instant::now(),
+            penalty: Duration::ZERO,
+        });
+
+        f(host)
+    }
+
+    /// Wait for a free connection slot of the host. Slot is held until the permit is dropped.
+    pub async fn connection(&self, host: &str) -> OwnedSemaphorePermit {
+        let connections = self.with_host(host, |host| Arc::clone(&host.connections));
+        connections.acquire_owned().await.unwrap()
+    }
+
+    /// Wait until a request to the host is allowed by its rate limit.
+    pub async fn request(&self, host: &str) {
+        let at = self.with_host(host, |host| {
+            let at = host.next_request.max(Instant::now());
+            host.next_request = at + self.interval + host.penalty;
+            at
+        });
+
+        tokio::time::sleep_until(at.into()).await;
+    }
+
+    /// Host responded with `429` or `503`. Hold off the requests and slow down.
+    pub fn throttled(&self, host: &str, retry_after: Option<Duration>) {
+        self.with_host(host, |host_state| {
+            host_state.penalty = (host_state.penalty * 2).clamp(BASE_PENALTY, MAX_PENALTY);
+
+            let wait = retry_after.unwrap_or(host_state.penalty);
+            host_state.next_request = host_state.next_request.max(Instant::now() + wait);
+            warn!("{host} asked to slow down; waiting {}s", wait.as_secs());
+        });
+    }
+
+    /// Request to the host succeeded. Recover from the slow down gradually.
+    pub fn succeeded(&self, host: &str) {
+        self.with_host(host, |host| {
+            host.penalty /= 2;
+            if host.penalty < BASE_PENALTY {
+                host.penalty = Duration::ZERO;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn connections_are_capped_per_host() {
+        let limiter = HostLimiter::new(2, None);
+
+        let first = limiter.connection("a.example").await;
+        let _second = limiter.connection("a.example").await;
+        let third =
+            tokio::time::timeout(Duration::from_millis(50), limiter.connection("a.example")).await;
+        assert!(third.is_err());
+
+        // Other hosts have slots of their own.
+        let _other = limiter.connection("b.example").await;
+
+        drop(first);
+        let third =
+            tokio::time::timeout(Duration::from_millis(50), limiter.connection("a.example")).await;
+        assert!(third.is_ok());
+    }
+
+    /// Penalty of the host, and how long until its next request.
+    fn delay(limiter: &HostLimiter, host: &str) -> (Duration, Duration) {
+        limiter.with_host(host, |host| {
+            (
+                host.penalty,
+                host.next_request.saturating_duration_since(Instant::now()),
+            )
+        })
+    }
+
+    #[test]
+    fn backoff_after_throttled() {
+        let limiter = HostLimiter::new(1, None);
+
+        limiter.throttled("a.example", None);
+        let (penalty, wait) = delay(&limiter, "a.example");
+        assert_eq!(penalty, BASE_PENALTY);
+        assert!(wait > BASE_PENALTY / 2 && wait <= BASE_PENALTY);
+
+        // Doubles with every throttle, Retry-After is waited for instead if given.
+        limiter.throttled("a.example", None);
+        assert_eq!(delay(&limiter, "a.example").0, BASE_PENALTY * 2);
+        limiter.throttled("a.example", Some(Duration::from_secs(30)));
+        let (penalty, wait) = delay(&limiter, "a.example");
+        assert_eq!(penalty, BASE_PENALTY * 4);
+        assert!(wait > Duration::from_secs(29));
+
+        for _ in 0..10 {
+            limiter.throttled("a.example", None);
+        }
+        assert_eq!(delay(&limiter, "a.example").0, MAX_PENALTY);
+
+        // Recovers halving the penalty on every success.
+        limiter.succeeded("a.example");
+        assert_eq!(delay(&limiter, "a.example").0, MAX_PENALTY / 2);
+        for _ in 0..6 {
+            limiter.succeeded("a.example");
+        }
+        assert!(delay(&limiter, "a.example").0.is_zero());
+    }
+
+    #[tokio::test]
+    async fn requests_are_spaced_by_the_penalty() {
+        let limiter = HostLimiter::new(1, Some(10.0));
+        limiter.with_host("a.example", |host| host.penalty = BASE_PENALTY);
+
+        limiter.request("a.example").await;
+        let (_, wait) = delay(&limiter, "a.example");
+        assert!(wait > BASE_PENALTY && wait <= BASE_PENALTY + Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn idle_hosts_are_removed() {
+        let limiter = HostLimiter::new(1, None);
+
+        let connection = limiter.connection("a.example").await;
+        limiter.with_host("b.example", |_| ());
+        assert_eq!(limiter.hosts.lock().unwrap().len(), 2);
+
+        limiter.with_host("c.example", |_| ());
+        let hosts = limiter.hosts.lock().unwrap();
+        assert!(hosts.contains_key("a.example"));
+        assert!(!hosts.contains_key("b.example"));
+        drop(hosts);
+
+        drop(connection);
+        limiter.with_host("c.example", |_| ());
+        assert_eq!(limiter.hosts.lock().unwrap().len(), 1);
+    }
+}
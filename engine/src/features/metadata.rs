@@ -0,0 +1,38 @@
+use filetime::FileTime;
+use tracing::warn;
+
+use std::path::Path;
+
+/// Extended attribute with the url the file was downloaded from, as set by browsers.
+const ORIGIN_URL: &str = "user.xdg.origin.url";
+/// Extended attribute with the page that linked to the file.
+const REFERRER_URL: &str = "user.xdg.referrer.url";
+
+/// Set the modification time of the file from the `Last-Modified` of the response.
+pub fn set_mtime(path: &Path, last_modified: &str) {
+    let Ok(modified) = httpdate::parse_http_date(last_modified) else {
+        warn!("Invalid Last-Modified of {path:?}: {last_modified}");
+        return;
+    };
+
+    if let Err(e) = filetime::set_file_mtime(path, FileTime::from_system_time(modified)) {
+        warn!("Failed to set the modification time of {path:?}: {e}");
+    }
+}
+
+/// Record where the file came from in its extended attributes.
+///
+/// Not every file system supports them, so failing is not an error of the download.
+pub fn set_origin(path: &Path, url: &str, referrer: Option<&str>) {
+    let attributes = [(ORIGIN_URL, Some(url)), (REFERRER_URL, referrer)];
+
+    for (name, value) in attributes {
+        let Some(value) = value else {
+            continue;
+        };
+
+        if let Err(e) = xattr::set(path, name, value.as_bytes()) {
+            warn!("Failed to set {name} of {path:?}: {e}");
+        }
+    }
+}
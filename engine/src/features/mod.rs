@@ -0,0 +1,6 @@
+mod download;
+mod host_limit;
+mod metadata;
+mod options;
+mod verify;
+pub use download::DownloadManager;
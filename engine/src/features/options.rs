@@ -0,0 +1,78 @@
+use dlm_protocol::DownloadOptions;
+
+use crate::utils::Config;
+use crate::utils::DownloadError;
+
+/// Settings and checks of [`DownloadOptions`] applied by the daemon.
+pub trait OptionsExt {
+    /// Fill the settings that are not given with the ones from daemon's configuration.
+    fn with_defaults(self, config: &Config) -> Self;
+
+    /// Check the `Content-Type` of the response against the expected ones.
+    fn check_content_type(&self, content_type: Option<&str>) -> Result<(), DownloadError>;
+
+    /// Check the size of the file against the limits. Unknown size always passes.
+    fn check_size(&self, size: usize) -> Result<(), DownloadError>;
+
+    /// Check the bytes received so far, or the announced size, against the maximum size.
+    fn check_max_size(&self, size: usize) -> Result<(), DownloadError>;
+}
+
+impl OptionsExt for DownloadOptions {
+    fn with_defaults(mut self, config: &Config) -> Self {
+        self.idle_timeout = self.idle_timeout.or(config.idle_timeout);
+        self.low_speed_limit = self.low_speed_limit.or(config.low_speed_limit);
+        self.low_speed_time = self.low_speed_time.or(Some(config.low_speed_time));
+        // Maximum size of the policy can only be lowered.
+        self.max_size = match (self.max_size, config.policy.max_size) {
+            (Some(max_size), Some(limit)) => Some(max_size.min(limit)),
+            (max_size, limit) => max_size.or(limit),
+        };
+        self
+    }
+
+    fn check_content_type(&self, content_type: Option<&str>) -> Result<(), DownloadError> {
+        let Some(expected) = &self.content_types else {
+            return Ok(());
+        };
+
+        // Drop the parameters. i.e. "text/html; charset=utf-8" -> "text/html"
+        let content_type = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        let matches = expected.iter().any(|expected| {
+            let expected = expected.trim().to_ascii_lowercase();
+            match expected.strip_suffix("/*") {
+                Some(kind) => content_type.split('/').next() == Some(kind),
+                None => content_type == expected,
+            }
+        });
+
+        if matches {
+            Ok(())
+        } else {
+            Err(DownloadError::ContentType(content_type))
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), DownloadError> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        if self.min_size.is_some_and(|min| size < min) {
+            return Err(DownloadError::Size(size));
+        }
+
+        self.check_max_size(size)
+    }
+
+    fn check_max_size(&self, size: usize) -> Result<(), DownloadError> {
+        match self.max_size {
+            Some(limit) if size > limit => Err(DownloadError::TooLarge { limit, size }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dlm_protocol::Algorithm;
+use dlm_protocol::Verification;
+use dlm_protocol::VerificationStatus;
+use md5::Md5;
+use reqwest::header::HeaderMap;
+use sha1::Sha1;
+use sha2::Digest;
+use sha2::Sha256;
+use sha2::Sha512;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+use std::path::Path;
+
+use crate::utils::DownloadError;
+
+/// Parse the algorithm name used in the `Digest` and `Repr-Digest` headers.
+fn algorithm_from_name(name: &str) -> Option<Algorithm> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "crc32" => Some(Algorithm::Crc32),
+        "crc32c" => Some(Algorithm::Crc32c),
+        "md5" => Some(Algorithm::Md5),
+        "sha" | "sha-1" | "sha1" => Some(Algorithm::Sha1),
+        "sha-256" | "sha256" => Some(Algorithm::Sha256),
+        "sha-512" | "sha512" => Some(Algorithm::Sha512),
+        _ => None,
+    }
+}
+
+/// Length of the digest in bytes.
+fn digest_len(algorithm: Algorithm) -> usize {
+    match algorithm {
+        Algorithm::Crc32 | Algorithm::Crc32c => 4,
+        Algorithm::Md5 => 16,
+        Algorithm::Sha1 => 20,
+        Algorithm::Sha256 => 32,
+        Algorithm::Sha512 => 64,
+    }
+}
+
+/// Digest of the file announced by the server.
+#[derive(Clone, Debug)]
+pub struct ExpectedDigest {
+    algorithm: Algorithm,
+    /// Header the digest was taken from.
+    header: &'static str,
+    value: Vec<u8>,
+}
+
+impl ExpectedDigest {
+    fn new(algorithm: Algorithm, header: &'static str, value: &str) -> Option<Self> {
+        let value = STANDARD.decode(value.trim()).ok()?;
+
+        // i.e. Composite checksums of multipart uploads are not digests of the file.
+        (value.len() == digest_len(algorithm)).then_some(Self {
+            algorithm,
+            header,
+            value,
+        })
+    }
+}
+
+/// Collect the digests of the whole file from the response headers.
+///
+/// `Content-MD5` covers only the response body, so it is used for full responses only.
+pub fn digests_from_headers(headers: &HeaderMap, full_response: bool) -> Vec<ExpectedDigest> {
+    let mut digests = Vec::new();
+
+    let values = |name: &'static str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect::<Vec<_>>()
+    };
+
+    // i.e. Repr-Digest: sha-256=:<base64>:
+    for (name, value) in values("repr-digest") {
+        if let Some(algorithm) = algorithm_from_name(name) {
+            digests.extend(ExpectedDigest::new(
+                algorithm,
+                "Repr-Digest",
+                value.trim_matches(':'),
+            ));
+        }
+    }
+
+    // i.e. Digest: SHA-256=<base64>
+    for (name, value) in values("digest") {
+        if let Some(algorithm) = algorithm_from_name(name) {
+            digests.extend(ExpectedDigest::new(algorithm, "Digest", value));
+        }
+    }
+
+    // i.e. x-goog-hash: crc32c=<base64>, md5=<base64>
+    for (name, value) in values("x-goog-hash") {
+        if let Some(algorithm) = algorithm_from_name(name) {
+            digests.extend(ExpectedDigest::new(algorithm, "x-goog-hash", value));
+        }
+    }
+
+    for (header, algorithm) in [
+        ("x-amz-checksum-sha256", Algorithm::Sha256),
+        ("x-amz-checksum-sha1", Algorithm::Sha1),
+        ("x-amz-checksum-crc32c", Algorithm::Crc32c),
+        ("x-amz-checksum-crc32", Algorithm::Crc32),
+    ] {
+        if let Some(value) = headers.get(header).and_then(|value| value.to_str().ok()) {
+            digests.extend(ExpectedDigest::new(algorithm, header, value));
+        }
+    }
+
+    if full_response {
+        if let Some(value) = headers
+            .get("content-md5")
+            .and_then(|value| value.to_str().ok())
+        {
+            digests.extend(ExpectedDigest::new(Algorithm::Md5, "Content-MD5", value));
+        }
+    }
+
+    digests
+}
+
+enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Crc32c(u32),
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            Algorithm::Crc32c => Hasher::Crc32c(0),
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32(hasher) => hasher.update(data),
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+            Hasher::Crc32c(crc) => crc.to_be_bytes().to_vec(),
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Verify the file against the strongest of the expected digests.
+///
+/// Returns `None` if there is nothing to verify against.
+pub async fn verify_file(
+    path: &Path,
+    digests: &[ExpectedDigest],
+) -> Result<Option<Verification>, DownloadError> {
+    // Algorithm is ordered from the weakest to the strongest.
+    let Some(expected) = digests
+        .iter()
+        .max_by_key(|digest| digest.algorithm as usize)
+    else {
+        return Ok(None);
+    };
+
+    let mut file = File::open(path).await?;
+    let mut hasher = Hasher::new(expected.algorithm);
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    let actual = hasher.finalize();
+    let status = if actual == expected.value {
+        VerificationStatus::Verified
+    } else {
+        VerificationStatus::Mismatch
+    };
+
+    Ok(Some(Verification {
+        algorithm: expected.algorithm,
+        header: expected.header.to_string(),
+        status,
+        expected: STANDARD.encode(&expected.value),
+        actual: STANDARD.encode(actual),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    const DATA: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn sha256() -> String {
+        STANDARD.encode(Sha256::digest(DATA))
+    }
+
+    fn md5() -> String {
+        STANDARD.encode(Md5::digest(DATA))
+    }
+
+    /// Write `DATA` to a file of its own in the temporary directory.
+    async fn data_file(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("dlm_verify_{}_{name}", std::process::id()));
+        tokio::fs::write(&path, DATA).await.unwrap();
+        path
+    }
+
+    #[test]
+    fn digest_header() {
+        let digests = digests_from_headers(
+            &headers(&[("digest", &format!("unixsum=30637, SHA-256={}", sha256()))]),
+            true,
+        );
+
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].algorithm, Algorithm::Sha256);
+        assert_eq!(digests[0].header, "Digest");
+        assert_eq!(digests[0].value, Sha256::digest(DATA).to_vec());
+    }
+
+    #[test]
+    fn repr_digest_header() {
+        let digests = digests_from_headers(
+            &headers(&[(
+                "repr-digest",
+                &format!("sha-256=:{}:, md5=:{}:", sha256(), md5()),
+            )]),
+            false,
+        );
+
+        let algorithms = digests
+            .iter()
+            .map(|digest| digest.algorithm)
+            .collect::<Vec<_>>();
+        assert_eq!(algorithms, [Algorithm::Sha256, Algorithm::Md5]);
+        assert!(digests.iter().all(|digest| digest.header == "Repr-Digest"));
+    }
+
+    #[test]
+    fn content_md5_only_for_full_responses() {
+        let headers = headers(&[("content-md5", &md5())]);
+
+        let digests = digests_from_headers(&headers, true);
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].algorithm, Algorithm::Md5);
+        assert_eq!(digests[0].header, "Content-MD5");
+
+        assert!(digests_from_headers(&headers, false).is_empty());
+    }
+
+    #[test]
+    fn invalid_digests_are_ignored() {
+        let digests = digests_from_headers(
+            &headers(&[
+                // Composite checksum of a multipart upload.
+                ("x-amz-checksum-sha256", &format!("{}AAAA", sha256())),
+                ("digest", "SHA-256=not base64"),
+                ("repr-digest", &format!("blake3=:{}:", sha256())),
+            ]),
+            true,
+        );
+
+        assert!(digests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn verified_with_the_strongest_digest() {
+        let path = data_file("verified").await;
+        let digests = digests_from_headers(
+            &headers(&[
+                ("content-md5", "AAAAAAAAAAAAAAAAAAAAAA=="),
+                ("digest", &format!("sha-256={}", sha256())),
+            ]),
+            true,
+        );
+
+        let verification = verify_file(&path, &digests).await.unwrap().unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(verification.algorithm, Algorithm::Sha256);
+        assert_eq!(verification.status, VerificationStatus::Verified);
+        assert_eq!(verification.actual, sha256());
+    }
+
+    #[tokio::test]
+    async fn mismatch() {
+        let path = data_file("mismatch").await;
+        let digests = digests_from_headers(
+            &headers(&[("content-md5", "AAAAAAAAAAAAAAAAAAAAAA==")]),
+            true,
+        );
+
+        let verification = verify_file(&path, &digests).await.unwrap().unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(verification.status, VerificationStatus::Mismatch);
+        assert_eq!(verification.header, "Content-MD5");
+        assert_eq!(verification.actual, md5());
+    }
+
+    #[tokio::test]
+    async fn nothing_to_verify() {
+        assert!(verify_file(Path::new("/nonexistent"), &[])
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,47 @@
+//! Download engine of `dlm_server`, usable without running the daemon.
+//!
+//! A [`DownloadManager`] runs the downloads in the background on the tokio
+//! runtime. Every change of a download is published as a [`DownloadSnapshot`]
+//! to the subscribers, and the downloads are controlled by their ids.
+//!
+//! ```no_run
+//! use dlm_engine::{Config, DownloadError, DownloadManager, DownloadOptions, State};
+//!
+//! # async fn run() -> Result<(), DownloadError> {
+//! let manager = DownloadManager::new(Config::default());
+//! let mut events = manager.subscribe();
+//!
+//! let url = "https://example.org/file.iso".to_string();
+//! manager.check_url(&url).await?;
+//! let ids = manager.enqueue(vec![url], DownloadOptions::default()).await;
+//!
+//! while let Ok(download) = events.recv().await {
+//!     match download.state {
+//!         State::Downloading if download.progress >= 50 => {
+//!             manager.pause_downloading(ids[0]).await?;
+//!             manager.resume_download(ids[0]).await?;
+//!         }
+//!         State::Completed | State::Failed => break,
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod features;
+mod utils;
+
+pub use dlm_protocol::Algorithm;
+pub use dlm_protocol::DownloadFailure;
+pub use dlm_protocol::DownloadOptions;
+pub use dlm_protocol::DownloadSnapshot;
+pub use dlm_protocol::FailureKind;
+pub use dlm_protocol::State;
+pub use dlm_protocol::Verification;
+pub use dlm_protocol::VerificationStatus;
+pub use features::DownloadManager;
+pub use utils::filter_name;
+pub use utils::Config;
+pub use utils::DownloadError;
+pub use utils::UrlPolicy;
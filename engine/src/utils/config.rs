@@ -0,0 +1,53 @@
+use serde::Deserialize;
+
+use super::os_download_dir;
+use super::UrlPolicy;
+
+use std::path::PathBuf;
+
+/// Settings of the downloads. Every field is optional when it is deserialized.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds without receiving any data before a download counts as stalled.
+    pub idle_timeout: Option<u64>,
+    /// Bytes per second under which a download counts as stalled ...
+    pub low_speed_limit: Option<usize>,
+    /// ... once the speed stays under it for this many seconds.
+    pub low_speed_time: u64,
+    /// Concurrent connections to a single host.
+    pub host_connections: usize,
+    /// Requests per second to a single host. Unlimited if not set.
+    pub host_requests_per_second: Option<f64>,
+    /// Set the modification time of the downloaded files from `Last-Modified`.
+    pub set_mtime: bool,
+    /// Record the url and referrer of the downloaded files in their extended attributes.
+    pub set_xattrs: bool,
+    /// Urls the daemon accepts.
+    pub policy: UrlPolicy,
+    /// Directory the files are downloaded to. `$HOME/Downloads` by default.
+    pub download_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Some(60),
+            low_speed_limit: None,
+            low_speed_time: 30,
+            host_connections: 4,
+            host_requests_per_second: None,
+            set_mtime: true,
+            set_xattrs: true,
+            policy: UrlPolicy::default(),
+            download_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Directory the files are downloaded to.
+    pub fn download_dir(&self) -> PathBuf {
+        self.download_dir.clone().unwrap_or_else(os_download_dir)
+    }
+}
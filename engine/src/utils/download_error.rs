@@ -0,0 +1,160 @@
+use dlm_protocol::DownloadFailure;
+use dlm_protocol::ErrorCode;
+use dlm_protocol::FailureKind;
+
+use std::time::Duration;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum DownloadError {
+    ReqwestError(reqwest::Error),
+    IoError(std::io::Error),
+    /// Server responded with a non-success status code.
+    HttpStatus(u16),
+    /// Server responded with `429` or `503`, optionally telling when to try again.
+    RateLimited {
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+    /// `Content-Type` of the response is not one of the expected ones.
+    ContentType(String),
+    /// Size of the file is out of the expected range.
+    Size(usize),
+    /// File is, or turned out to be while downloading, larger than the maximum size.
+    TooLarge {
+        limit: usize,
+        size: usize,
+    },
+    /// Download did not complete within the given seconds.
+    Deadline(u64),
+    /// Connection closed before `expected` bytes were received.
+    Truncated {
+        expected: usize,
+        received: usize,
+    },
+    /// Downloaded file does not match the digest provided by the server.
+    Integrity(String),
+    /// Server stopped sending data, or is sending it too slowly.
+    Stalled(String),
+    /// Url is rejected by the policy of the daemon.
+    Policy(String),
+    Other(String),
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::ReqwestError(e) => write!(f, "Reqwest : {:#?}", e),
+            DownloadError::IoError(e) => write!(f, "Io Error: {:#?}", e),
+            DownloadError::HttpStatus(status) => write!(f, "Http status: {status}"),
+            DownloadError::RateLimited { status, .. } => write!(f, "Rate limited: {status}"),
+            DownloadError::ContentType(e) => write!(f, "Unexpected content type: {e}"),
+            DownloadError::Size(size) => write!(f, "Size out of range: {size} bytes"),
+            DownloadError::TooLarge { limit, size } => {
+                write!(f, "Larger than {limit} bytes: {size} bytes")
+            }
+            DownloadError::Deadline(seconds) => write!(f, "Not completed within {seconds}s"),
+            DownloadError::Truncated { expected, received } => {
+                write!(f, "Transfer truncated: {received} of {expected} bytes")
+            }
+            DownloadError::Integrity(e) => write!(f, "Digest mismatch: {e}"),
+            DownloadError::Stalled(e) => write!(f, "Stalled: {e}"),
+            DownloadError::Policy(e) => write!(f, "Rejected by policy: {e}"),
+            DownloadError::Other(e) => write!(f, "Error occured: {:#?}", e),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(value: reqwest::Error) -> Self {
+        DownloadError::ReqwestError(value)
+    }
+}
+
+impl From<std::io::Error> for DownloadError {
+    fn from(value: std::io::Error) -> Self {
+        DownloadError::IoError(value)
+    }
+}
+
+impl DownloadError {
+    /// Category of the error reported to the client.
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            DownloadError::ReqwestError(_) => FailureKind::Network,
+            DownloadError::IoError(_) => FailureKind::Io,
+            DownloadError::HttpStatus(_) | DownloadError::RateLimited { .. } => FailureKind::Http,
+            DownloadError::ContentType(_) => FailureKind::ContentType,
+            DownloadError::Size(_) => FailureKind::Size,
+            DownloadError::TooLarge { .. } => FailureKind::TooLarge,
+            DownloadError::Deadline(_) => FailureKind::Deadline,
+            DownloadError::Truncated { .. } => FailureKind::Truncated,
+            DownloadError::Integrity(_) => FailureKind::Integrity,
+            DownloadError::Stalled(_) => FailureKind::Stalled,
+            DownloadError::Policy(_) => FailureKind::Policy,
+            DownloadError::Other(_) => FailureKind::Other,
+        }
+    }
+
+    /// Whether trying again could succeed.
+    ///
+    /// Client errors (except timeout and rate limit) and failed checks are not retried.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::HttpStatus(status) => {
+                !(400..500).contains(status) || *status == 408 || *status == 429
+            }
+            DownloadError::ContentType(_)
+            | DownloadError::Size(_)
+            | DownloadError::TooLarge { .. }
+            | DownloadError::Deadline(_)
+            | DownloadError::Integrity(_)
+            | DownloadError::Policy(_) => false,
+            _ => true,
+        }
+    }
+
+    /// HTTP status code, if the error came with one.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            DownloadError::ReqwestError(e) => e.status().map(|status| status.as_u16()),
+            DownloadError::HttpStatus(status) | DownloadError::RateLimited { status, .. } => {
+                Some(*status)
+            }
+            _ => None,
+        }
+    }
+
+    /// Short, single line description of the error.
+    pub fn message(&self) -> String {
+        match self {
+            DownloadError::ReqwestError(e) => e.to_string(),
+            DownloadError::IoError(e) => e.to_string(),
+            DownloadError::Integrity(e)
+            | DownloadError::Stalled(e)
+            | DownloadError::Policy(e)
+            | DownloadError::Other(e) => e.clone(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Why the download failed, as reported to the client.
+    pub fn failure(&self, attempts: usize) -> DownloadFailure {
+        DownloadFailure {
+            kind: self.kind(),
+            status: self.status(),
+            message: self.message(),
+            attempts,
+        }
+    }
+
+    /// Code of the error replied to the request that caused it.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DownloadError::Policy(_) => ErrorCode::Rejected,
+            _ => ErrorCode::Failed,
+        }
+    }
+}
@@ -0,0 +1,15 @@
+mod config;
+mod download_error;
+mod os_config;
+mod url_policy;
+mod util;
+
+pub use config::Config;
+pub use download_error::DownloadError;
+pub use os_config::os_download_dir;
+pub(crate) use url_policy::private_address;
+pub(crate) use url_policy::PublicResolver;
+pub use url_policy::UrlPolicy;
+pub use util::filter_name;
+pub use util::url_host;
+pub use util::validate_url;
@@ -0,0 +1,287 @@
+use reqwest::dns::Addrs;
+use reqwest::dns::Name;
+use reqwest::dns::Resolve;
+use reqwest::dns::Resolving;
+use serde::Deserialize;
+use tokio::net::lookup_host;
+use url::Host;
+use url::Url;
+
+use std::error::Error;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+
+use super::DownloadError;
+
+/// Urls the daemon accepts, checked when a command is submitted and on every redirect.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct UrlPolicy {
+    pub allowed_schemes: Vec<String>,
+    /// Any host is allowed if empty. `*.example.com` matches `example.com` and its subdomains.
+    pub allowed_hosts: Vec<String>,
+    /// Rejected even if they are allowed. Same patterns as `allowed_hosts`.
+    pub denied_hosts: Vec<String>,
+    /// Reject the hosts that are, or resolve to, loopback, private or link-local addresses.
+    pub block_private: bool,
+    /// Maximum size of a file in bytes. Caps the `max_size` of every download.
+    pub max_size: Option<usize>,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            block_private: false,
+            max_size: None,
+        }
+    }
+}
+
+impl UrlPolicy {
+    /// Check the url against the policy.
+    ///
+    /// Addresses of the host are checked here to reject it early. The client of a download
+    /// checks them again on every connection, see [`PublicResolver`].
+    pub async fn check(&self, url: &str) -> Result<(), DownloadError> {
+        let url = Url::parse(url).map_err(|e| DownloadError::Policy(e.to_string()))?;
+
+        let scheme = url.scheme();
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+        {
+            return Err(DownloadError::Policy(format!(
+                "Scheme {scheme} is not allowed"
+            )));
+        }
+
+        let Some(host) = url.host() else {
+            return Err(DownloadError::Policy("Url has no host".to_string()));
+        };
+        let name = host.to_string().to_ascii_lowercase();
+        let name = name.trim_end_matches('.');
+
+        if self
+            .denied_hosts
+            .iter()
+            .any(|pattern| host_matches(pattern, name))
+        {
+            return Err(DownloadError::Policy(format!("Host {name} is denied")));
+        }
+
+        if !self.allowed_hosts.is_empty()
+            && !self
+                .allowed_hosts
+                .iter()
+                .any(|pattern| host_matches(pattern, name))
+        {
+            return Err(DownloadError::Policy(format!("Host {name} is not allowed")));
+        }
+
+        if self.block_private {
+            let addresses: Vec<IpAddr> = match host {
+                Host::Ipv4(ip) => vec![ip.into()],
+                Host::Ipv6(ip) => vec![ip.into()],
+                Host::Domain(domain) => {
+                    let port = url.port_or_known_default().unwrap_or(0);
+                    lookup_host((domain, port))
+                        .await
+                        .map_err(|e| {
+                            DownloadError::Policy(format!("Failed to resolve {domain}: {e}"))
+                        })?
+                        .map(|address| address.ip())
+                        .collect()
+                }
+            };
+
+            if let Some(ip) = addresses.into_iter().find(is_private) {
+                return Err(DownloadError::Policy(format!(
+                    "Host {name} is a private address: {ip}"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolver of the http clients if private addresses are blocked.
+///
+/// Checks the addresses that are connected to, so a host can not resolve to a public
+/// address when the url is checked and to a private one when it is downloaded.
+#[derive(Debug)]
+pub(crate) struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let name = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(address) = addresses.iter().find(|address| is_private(&address.ip())) {
+                return Err(Box::new(PrivateAddress(format!(
+                    "Host {name} is a private address: {}",
+                    address.ip()
+                ))) as _);
+            }
+
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Connection refused by [`PublicResolver`].
+#[derive(Debug)]
+struct PrivateAddress(String);
+
+impl std::fmt::Display for PrivateAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for PrivateAddress {}
+
+/// Why the request failed, if [`PublicResolver`] refused to connect.
+pub(crate) fn private_address(e: &reqwest::Error) -> Option<String> {
+    let mut source = e.source();
+    while let Some(error) = source {
+        if let Some(private) = error.downcast_ref::<PrivateAddress>() {
+            return Some(private.0.clone());
+        }
+        source = error.source();
+    }
+
+    None
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+        None => host == pattern,
+    }
+}
+
+/// Loopback, private, link-local, shared (CGNAT) and unspecified addresses.
+fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || (first == 100 && (second & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => {
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                || ip
+                    .to_ipv4_mapped()
+                    .is_some_and(|ip| is_private(&IpAddr::V4(ip)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn host_patterns() {
+        for (pattern, host, expected) in [
+            ("example.com", "example.com", true),
+            ("Example.COM", "example.com", true),
+            ("example.com", "www.example.com", false),
+            ("*.example.com", "example.com", true),
+            ("*.example.com", "www.example.com", true),
+            ("*.example.com", "a.b.example.com", true),
+            ("*.example.com", "badexample.com", false),
+            ("*.example.com", "example.com.evil.org", false),
+            ("example.com", "example.com:8080", false),
+            ("127.0.0.1", "127.0.0.1", true),
+            ("[::1]", "[::1]", true),
+        ] {
+            assert_eq!(host_matches(pattern, host), expected, "{pattern} {host}");
+        }
+    }
+
+    #[test]
+    fn private_addresses() {
+        for (ip, expected) in [
+            ("127.0.0.1", true),
+            ("10.1.2.3", true),
+            ("172.16.0.1", true),
+            ("172.32.0.1", false),
+            ("192.168.1.1", true),
+            ("169.254.169.254", true),
+            ("100.64.0.1", true),
+            ("100.128.0.1", false),
+            ("0.0.0.0", true),
+            ("255.255.255.255", true),
+            ("93.184.216.34", false),
+            ("::1", true),
+            ("::", true),
+            ("fc00::1", true),
+            ("fd12:3456::1", true),
+            ("fe80::1", true),
+            ("::ffff:127.0.0.1", true),
+            ("::ffff:10.0.0.1", true),
+            ("::ffff:93.184.216.34", false),
+            ("2606:2800:220:1:248:1893:25c8:1946", false),
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(is_private(&ip), expected, "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn private_hosts_are_rejected() {
+        let policy = UrlPolicy {
+            block_private: true,
+            ..Default::default()
+        };
+
+        for url in [
+            "http://127.0.0.1/file",
+            "http://[::1]:8080/file",
+            "http://[::ffff:192.168.0.1]/file",
+            "http://localhost/file",
+        ] {
+            assert!(
+                matches!(policy.check(url).await, Err(DownloadError::Policy(_))),
+                "{url}"
+            );
+        }
+        assert!(UrlPolicy::default()
+            .check("http://localhost/file")
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_private_addresses() {
+        let client = reqwest::Client::builder()
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()
+            .unwrap();
+
+        let e = client.get("http://localhost:9/").send().await.unwrap_err();
+        let message = private_address(&e).unwrap();
+        assert!(
+            message.starts_with("Host localhost is a private address"),
+            "{message}"
+        );
+    }
+}
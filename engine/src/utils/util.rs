@@ -1,4 +1,3 @@
-use url;
 use url::Url;
 
 /// Validate the url before processing.
@@ -7,6 +6,19 @@ pub fn validate_url(url: &str) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Host (and port) of the url. Whole url if it has none.
+pub fn url_host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.host_str().map(|host| match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host.to_string(),
+            })
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
 /// Removes '.' '/' '\' ':'  from the url string
 pub fn filter_name(name: String) -> String {
     let name_iter: Vec<&str> = name
@@ -0,0 +1,132 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::DownloadOptions;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum State {
+    Downloading,
+    Paused,
+    Completed,
+    Canceled,
+    Pending,
+    Retrying,
+    Failed,
+    /// File on disk is the same as the remote one. Only in update mode.
+    UpToDate,
+}
+
+impl State {
+    /// Whether the download is over. It does not continue unless it is retried or added again.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            State::Completed | State::Canceled | State::Failed | State::UpToDate
+        )
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A download as sent to the clients.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DownloadSnapshot {
+    pub id: usize,
+    pub progress: usize,
+    pub url: String,
+    pub total_length: usize,
+    /// Bytes already written to the partial file.
+    pub downloaded: usize,
+    pub destination: PathBuf,
+    pub state: State,
+    /// Number of attempts made by the current download run.
+    pub attempts: usize,
+    /// Last error, set while retrying and once the download has failed.
+    pub error: Option<DownloadFailure>,
+    pub options: DownloadOptions,
+    /// Urls the last request was redirected through, in order.
+    pub redirects: Vec<String>,
+    /// `ETag` of the resource.
+    pub etag: Option<String>,
+    /// `Last-Modified` of the resource.
+    pub last_modified: Option<String>,
+    pub verification: Option<Verification>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FailureKind {
+    Network,
+    Io,
+    Http,
+    ContentType,
+    Size,
+    TooLarge,
+    Deadline,
+    Truncated,
+    Integrity,
+    Stalled,
+    Policy,
+    Other,
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Why a download failed, attached to the download once it is retrying or has failed.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DownloadFailure {
+    pub kind: FailureKind,
+    pub status: Option<u16>,
+    pub message: String,
+    pub attempts: usize,
+}
+
+/// Ordered from the weakest to the strongest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Algorithm {
+    Crc32,
+    Crc32c,
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VerificationStatus {
+    Verified,
+    Mismatch,
+}
+
+impl fmt::Display for VerificationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Result of checking the downloaded file against the server provided digest.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Verification {
+    pub algorithm: Algorithm,
+    /// Header the digest was taken from.
+    pub header: String,
+    pub status: VerificationStatus,
+    pub expected: String,
+    pub actual: String,
+}
@@ -0,0 +1,206 @@
+//! Messages exchanged between `dlm_server` and its clients, one JSON object per line.
+//!
+//! A connection starts with [`Command::Hello`]. Every request carries a
+//! `request_id`, which is sent back with its [`ServerMessage::Reply`] or
+//! [`ServerMessage::Error`]. Changes of the downloads are sent as events
+//! once the handshake is done.
+//!
+//! Messages go over any `Transport`: the Unix socket, TCP, or the standard
+//! input and output of the server. It is provided with the `transport` feature,
+//! without it the crate only has the serde types.
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::env;
+use std::path::PathBuf;
+
+mod download;
+mod options;
+mod recurring;
+#[cfg(feature = "transport")]
+mod transport;
+
+pub use download::Algorithm;
+pub use download::DownloadFailure;
+pub use download::DownloadSnapshot;
+pub use download::FailureKind;
+pub use download::State;
+pub use download::Verification;
+pub use download::VerificationStatus;
+pub use options::DownloadOptions;
+pub use recurring::RecurringDownload;
+pub use recurring::Run;
+#[cfg(feature = "transport")]
+pub use transport::stdio;
+#[cfg(feature = "transport")]
+pub use transport::Transport;
+
+/// Version of the protocol spoken by this crate.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest version of the protocol still supported by the server.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Configuration file of the server: `$DLM_CONFIG`, or `$XDG_CONFIG_HOME/dlm_rust/config.json`
+/// (`$HOME/.config/dlm_rust/config.json`).
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("DLM_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("dlm_rust").join("config.json"))
+}
+
+/// Socket the server listens on: `$DLM_SOCKET`, the `socket` of its configuration file,
+/// or `$XDG_RUNTIME_DIR/dlm_rust/SOCKET`.
+///
+/// `/tmp/dlm_rust/SOCKET` without a runtime directory.
+pub fn socket_path(configured: Option<PathBuf>) -> PathBuf {
+    if let Some(path) = env::var_os("DLM_SOCKET") {
+        return PathBuf::from(path);
+    }
+    if let Some(path) = configured {
+        return path;
+    }
+
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("dlm_rust")
+        .join("SOCKET")
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Request {
+    /// Chosen by the client, sent back with the reply.
+    pub request_id: u64,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "command")]
+pub enum Command {
+    /// Handshake. `version` is the newest version the client speaks.
+    Hello {
+        version: u32,
+        /// Pre-shared token, required over TCP.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    Download {
+        urls: Vec<String>,
+        #[serde(default)]
+        options: DownloadOptions,
+    },
+    Pause {
+        id: usize,
+    },
+    Resume {
+        id: usize,
+    },
+    Retry {
+        id: usize,
+    },
+    Cancel {
+        id: usize,
+        /// Also remove the partially downloaded file.
+        #[serde(default)]
+        delete: bool,
+    },
+    UpdateUrl {
+        id: usize,
+        url: String,
+    },
+    List,
+    AddRecurring {
+        url: String,
+        /// i.e. "0 3 * * *"
+        cron: String,
+        /// Path template of the downloaded file.
+        destination: Option<String>,
+        #[serde(default)]
+        options: DownloadOptions,
+    },
+    RemoveRecurring {
+        id: usize,
+    },
+    TriggerRecurring {
+        id: usize,
+    },
+    ListRecurring,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Command of the request succeeded.
+    Reply {
+        request_id: u64,
+        #[serde(flatten)]
+        response: Response,
+    },
+    /// Command of the request failed. No `request_id` if the request could not be read.
+    Error {
+        request_id: Option<u64>,
+        code: ErrorCode,
+        message: String,
+    },
+    /// A download changed.
+    Download { download: Box<DownloadSnapshot> },
+    /// Current state of every download, sent when the client fell behind the changes.
+    Snapshot { downloads: Vec<DownloadSnapshot> },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    /// Handshake is done, `version` is used from now on.
+    Welcome {
+        version: u32,
+    },
+    Done,
+    /// Ids of the added downloads, and the urls that were rejected.
+    Added {
+        ids: Vec<usize>,
+        rejected: Vec<Rejected>,
+    },
+    Downloads {
+        downloads: Vec<DownloadSnapshot>,
+    },
+    RecurringAdded {
+        id: usize,
+    },
+    /// Download started by the run of a recurring download.
+    Triggered {
+        download_id: usize,
+    },
+    Recurring {
+        recurring: Vec<RecurringDownload>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Rejected {
+    pub url: String,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// None of the versions spoken by the client is supported.
+    UnsupportedVersion,
+    /// A command was sent before the handshake.
+    HandshakeRequired,
+    /// User of the client is not allowed to use the daemon.
+    Unauthorized,
+    /// Request could not be read, or the command is unknown.
+    InvalidRequest,
+    /// Url is rejected by the policy of the daemon.
+    Rejected,
+    Failed,
+}
@@ -0,0 +1,31 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::path::PathBuf;
+
+/// Optional checks and settings of a download, passed along with the urls.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DownloadOptions {
+    /// Accepted `Content-Type`s. `type/*` matches any subtype.
+    pub content_types: Option<Vec<String>>,
+    /// Minimum size of the file in bytes.
+    pub min_size: Option<usize>,
+    /// Maximum size of the file in bytes. Checked against `Content-Length` and while downloading.
+    pub max_size: Option<usize>,
+    /// Seconds without receiving any data before the download counts as stalled.
+    pub idle_timeout: Option<u64>,
+    /// Bytes per second under which the download counts as stalled ...
+    pub low_speed_limit: Option<usize>,
+    /// ... once the speed stays under it for this many seconds.
+    pub low_speed_time: Option<u64>,
+    /// Download only if the remote file changed since the last download,
+    /// using `If-None-Match` and `If-Modified-Since`.
+    pub update: bool,
+    /// Path of the file, relative to the download directory. Named after the url if not given.
+    pub destination: Option<PathBuf>,
+    /// Page the url was found on. Sent as `Referer` and recorded with the file.
+    pub referrer: Option<String>,
+    /// Seconds the download may take since it was added, including pauses and retries.
+    pub deadline: Option<u64>,
+}
@@ -0,0 +1,33 @@
+use chrono::DateTime;
+use chrono::Local;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::DownloadOptions;
+
+/// A single run of a recurring download.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Run {
+    pub at: DateTime<Local>,
+    /// Id of the download created by the run.
+    pub download_id: usize,
+}
+
+/// Url downloaded again and again on a cron schedule.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RecurringDownload {
+    pub id: usize,
+    pub url: String,
+    /// i.e. "0 3 * * *" for every day at 03:00. Seconds can be given as the first field.
+    pub cron: String,
+    /// Path of the file, relative to the download directory.
+    ///
+    /// `{name}`, `{date}`, `{time}` and `{run}` are replaced on each run.
+    pub destination: Option<String>,
+    pub options: DownloadOptions,
+    pub next_run: Option<DateTime<Local>>,
+    /// Number of runs so far.
+    #[serde(default)]
+    pub runs: usize,
+    pub history: Vec<Run>,
+}
@@ -0,0 +1,71 @@
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::DuplexStream;
+use tokio::io::Join;
+use tokio::io::ReadHalf;
+use tokio::io::Stdin;
+use tokio::io::Stdout;
+use tokio::io::WriteHalf;
+use tokio::net::tcp;
+use tokio::net::unix;
+use tokio::net::TcpStream;
+use tokio::net::UnixStream;
+
+/// Connection the messages are exchanged over.
+///
+/// Split in two, so the messages can be read and written from different tasks.
+/// Any other stream, i.e. a TLS one, can be split with [`tokio::io::split`] and
+/// joined back with [`tokio::io::join`].
+pub trait Transport: Send + 'static {
+    type Reader: AsyncRead + Send + Unpin + 'static;
+    type Writer: AsyncWrite + Send + Unpin + 'static;
+
+    fn into_split(self) -> (Self::Reader, Self::Writer);
+}
+
+impl Transport for UnixStream {
+    type Reader = unix::OwnedReadHalf;
+    type Writer = unix::OwnedWriteHalf;
+
+    fn into_split(self) -> (Self::Reader, Self::Writer) {
+        UnixStream::into_split(self)
+    }
+}
+
+impl Transport for TcpStream {
+    type Reader = tcp::OwnedReadHalf;
+    type Writer = tcp::OwnedWriteHalf;
+
+    fn into_split(self) -> (Self::Reader, Self::Writer) {
+        TcpStream::into_split(self)
+    }
+}
+
+/// In-memory pipe, see [`tokio::io::duplex`].
+impl Transport for DuplexStream {
+    type Reader = ReadHalf<DuplexStream>;
+    type Writer = WriteHalf<DuplexStream>;
+
+    fn into_split(self) -> (Self::Reader, Self::Writer) {
+        tokio::io::split(self)
+    }
+}
+
+/// Reader and writer of separate streams, i.e. [`stdio`] or the pipes of a subprocess.
+impl<R, W> Transport for Join<R, W>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    type Reader = R;
+    type Writer = W;
+
+    fn into_split(self) -> (Self::Reader, Self::Writer) {
+        self.into_inner()
+    }
+}
+
+/// Standard input and output of the process, i.e. of the server running as a subprocess.
+pub fn stdio() -> Join<Stdin, Stdout> {
+    tokio::io::join(tokio::io::stdin(), tokio::io::stdout())
+}
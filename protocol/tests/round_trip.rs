@@ -0,0 +1,284 @@
+use chrono::Local;
+use chrono::TimeZone;
+use dlm_protocol::Algorithm;
+use dlm_protocol::Command;
+use dlm_protocol::DownloadFailure;
+use dlm_protocol::DownloadOptions;
+use dlm_protocol::DownloadSnapshot;
+use dlm_protocol::ErrorCode;
+use dlm_protocol::FailureKind;
+use dlm_protocol::RecurringDownload;
+use dlm_protocol::Rejected;
+use dlm_protocol::Request;
+use dlm_protocol::Response;
+use dlm_protocol::Run;
+use dlm_protocol::ServerMessage;
+use dlm_protocol::State;
+use dlm_protocol::Verification;
+use dlm_protocol::VerificationStatus;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: T) {
+    let line = serde_json::to_string(&value).unwrap();
+    let parsed: T = serde_json::from_str(&line).unwrap();
+    assert_eq!(parsed, value, "{line}");
+}
+
+fn download(id: usize, state: State) -> DownloadSnapshot {
+    DownloadSnapshot {
+        id,
+        progress: 40,
+        url: "https://example.org/file.iso".to_string(),
+        total_length: 1000,
+        downloaded: 400,
+        destination: PathBuf::from("/home/user/Downloads/file.iso"),
+        state,
+        attempts: 1,
+        error: None,
+        options: DownloadOptions::default(),
+        redirects: vec!["https://mirror.example.org/file.iso".to_string()],
+        etag: Some("\"abc\"".to_string()),
+        last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        verification: None,
+    }
+}
+
+#[test]
+fn requests() {
+    let commands = [
+        Command::Hello {
+            version: 1,
+            token: None,
+        },
+        Command::Hello {
+            version: 1,
+            token: Some("secret".to_string()),
+        },
+        Command::Download {
+            urls: vec!["https://example.org/a".to_string()],
+            options: DownloadOptions {
+                content_types: Some(vec!["image/*".to_string()]),
+                max_size: Some(1024),
+                update: true,
+                destination: Some(PathBuf::from("images/a.png")),
+                referrer: Some("https://example.org".to_string()),
+                deadline: Some(60),
+                ..Default::default()
+            },
+        },
+        Command::Pause { id: 1 },
+        Command::Resume { id: 1 },
+        Command::Retry { id: 1 },
+        Command::Cancel {
+            id: 1,
+            delete: true,
+        },
+        Command::UpdateUrl {
+            id: 1,
+            url: "https://example.org/b".to_string(),
+        },
+        Command::List,
+        Command::AddRecurring {
+            url: "https://example.org/feed.xml".to_string(),
+            cron: "0 3 * * *".to_string(),
+            destination: Some("feeds/{date}-{name}".to_string()),
+            options: DownloadOptions::default(),
+        },
+        Command::RemoveRecurring { id: 2 },
+        Command::TriggerRecurring { id: 2 },
+        Command::ListRecurring,
+    ];
+
+    for (request_id, command) in commands.into_iter().enumerate() {
+        round_trip(Request {
+            request_id: request_id as u64,
+            command,
+        });
+    }
+}
+
+#[test]
+fn request_defaults() {
+    let request: Request = serde_json::from_value(json!({
+        "request_id": 3,
+        "command": "Download",
+        "urls": ["https://example.org/a"],
+    }))
+    .unwrap();
+    assert_eq!(
+        request.command,
+        Command::Download {
+            urls: vec!["https://example.org/a".to_string()],
+            options: DownloadOptions::default(),
+        }
+    );
+
+    let request: Request =
+        serde_json::from_value(json!({"request_id": 4, "command": "Cancel", "id": 1})).unwrap();
+    assert_eq!(
+        request.command,
+        Command::Cancel {
+            id: 1,
+            delete: false
+        }
+    );
+}
+
+#[test]
+fn replies() {
+    let mut failed = download(2, State::Retrying);
+    failed.error = Some(DownloadFailure {
+        kind: FailureKind::Http,
+        status: Some(503),
+        message: "Rate limited: 503".to_string(),
+        attempts: 2,
+    });
+
+    let responses = [
+        Response::Welcome { version: 1 },
+        Response::Done,
+        Response::Added {
+            ids: vec![1, 2],
+            rejected: vec![Rejected {
+                url: "http://localhost/".to_string(),
+                message: "localhost resolves to a private address".to_string(),
+            }],
+        },
+        Response::Downloads {
+            downloads: vec![download(1, State::Downloading), failed],
+        },
+        Response::RecurringAdded { id: 1 },
+        Response::Triggered { download_id: 3 },
+        Response::Recurring {
+            recurring: vec![RecurringDownload {
+                id: 1,
+                url: "https://example.org/feed.xml".to_string(),
+                cron: "0 3 * * *".to_string(),
+                destination: None,
+                options: DownloadOptions::default(),
+                next_run: Local.with_ymd_and_hms(2025, 1, 2, 3, 0, 0).single(),
+                runs: 1,
+                history: vec![Run {
+                    at: Local.with_ymd_and_hms(2025, 1, 1, 3, 0, 0).unwrap(),
+                    download_id: 3,
+                }],
+            }],
+        },
+    ];
+
+    for (request_id, response) in responses.into_iter().enumerate() {
+        round_trip(ServerMessage::Reply {
+            request_id: request_id as u64,
+            response,
+        });
+    }
+}
+
+#[test]
+fn errors() {
+    round_trip(ServerMessage::Error {
+        request_id: Some(1),
+        code: ErrorCode::Rejected,
+        message: "Scheme ftp is not allowed".to_string(),
+    });
+    round_trip(ServerMessage::Error {
+        request_id: None,
+        code: ErrorCode::InvalidRequest,
+        message: "expected value at line 1 column 1".to_string(),
+    });
+}
+
+#[test]
+fn events() {
+    let mut completed = download(1, State::Completed);
+    completed.verification = Some(Verification {
+        algorithm: Algorithm::Sha256,
+        header: "Repr-Digest".to_string(),
+        status: VerificationStatus::Verified,
+        expected: "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string(),
+        actual: "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string(),
+    });
+
+    round_trip(ServerMessage::Download {
+        download: Box::new(completed),
+    });
+    round_trip(ServerMessage::Snapshot {
+        downloads: [
+            State::Downloading,
+            State::Paused,
+            State::Canceled,
+            State::Pending,
+            State::Failed,
+            State::UpToDate,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, state)| download(id, state))
+        .collect(),
+    });
+}
+
+/// Messages keep the shape documented in the README.
+#[test]
+fn wire_format() {
+    let request: Request =
+        serde_json::from_value(json!({"request_id": 0, "command": "Hello", "version": 1})).unwrap();
+    assert_eq!(
+        request,
+        Request {
+            request_id: 0,
+            command: Command::Hello {
+                version: 1,
+                token: None,
+            },
+        }
+    );
+
+    let hello = Request {
+        request_id: 0,
+        command: Command::Hello {
+            version: 1,
+            token: Some("secret".to_string()),
+        },
+    };
+    assert_eq!(
+        serde_json::to_value(&hello).unwrap(),
+        json!({"request_id": 0, "command": "Hello", "version": 1, "token": "secret"})
+    );
+
+    let welcome = ServerMessage::Reply {
+        request_id: 0,
+        response: Response::Welcome { version: 1 },
+    };
+    assert_eq!(
+        serde_json::to_value(&welcome).unwrap(),
+        json!({"type": "reply", "request_id": 0, "response": "welcome", "version": 1})
+    );
+
+    let error = ServerMessage::Error {
+        request_id: Some(1),
+        code: ErrorCode::Failed,
+        message: "Download 3 is Completed, not downloading".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_value(&error).unwrap(),
+        json!({
+            "type": "error",
+            "request_id": 1,
+            "code": "failed",
+            "message": "Download 3 is Completed, not downloading",
+        })
+    );
+
+    let event = serde_json::to_value(ServerMessage::Download {
+        download: Box::new(download(1, State::UpToDate)),
+    })
+    .unwrap();
+    assert_eq!(event["type"], "download");
+    assert_eq!(event["download"]["state"], "UpToDate");
+}
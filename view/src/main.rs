@@ -1,17 +1,16 @@
+use dlm_control::socket_path;
+use dlm_control::Client;
+use dlm_control::ClientError;
+use dlm_protocol::Command;
+use dlm_protocol::DownloadFailure;
+use dlm_protocol::DownloadSnapshot;
+use dlm_protocol::FailureKind;
 use serde::Deserialize;
 use serde::Serialize;
-use tokio::io::AsyncBufReadExt;
-use tokio::io::AsyncWriteExt;
-use tokio::io::BufReader;
-use tokio::net::UnixStream;
-use tokio::sync::mpsc;
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::sync::mpsc::UnboundedSender;
 use tracing::error;
 use tui::CommandTab;
 
-use std::error::Error;
-use std::path::Path;
+use std::env;
 use std::path::PathBuf;
 
 mod tui;
@@ -21,96 +20,87 @@ pub struct CommandArgument {
     command: CommandTab,
     urls: Option<Vec<String>>,
     id: Option<usize>,
+    delete: Option<bool>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct SingleDownload {
-    id: usize,
-    progress: usize,
-    url: String,
-    total_length: usize,
-    destination: PathBuf,
-    state: String,
+impl CommandArgument {
+    /// Command to send to the server. None if an argument is missing.
+    pub fn command(self) -> Option<Command> {
+        let url = self.urls.as_ref().and_then(|urls| urls.first()).cloned();
+
+        let command = match self.command {
+            CommandTab::Download => Command::Download {
+                urls: self.urls?,
+                options: Default::default(),
+            },
+            CommandTab::Pause => Command::Pause { id: self.id? },
+            CommandTab::Resume => Command::Resume { id: self.id? },
+            CommandTab::Retry => Command::Retry { id: self.id? },
+            CommandTab::Cancel => Command::Cancel {
+                id: self.id?,
+                delete: self.delete.unwrap_or(false),
+            },
+            CommandTab::UpdateUrl => Command::UpdateUrl {
+                id: self.id?,
+                url: url?,
+            },
+        };
+
+        Some(command)
+    }
 }
 
-pub async fn connect_socket() -> Result<UnixStream, Box<dyn std::error::Error>> {
-    let socket_path = Path::new("/tmp/dlm_rust").join("SOCKET");
-    let stream: UnixStream = UnixStream::connect(&socket_path).await?;
+/// Shown next to the state: why the download is failing or how it was verified.
+pub fn detail(download: &DownloadSnapshot) -> Option<String> {
+    if let Some(error) = &download.error {
+        return Some(reason(error));
+    }
 
-    Ok(stream)
+    download
+        .verification
+        .as_ref()
+        .map(|verification| format!("{} {}", verification.algorithm, verification.status))
 }
 
-pub async fn send_command(
-    mut write_half: tokio::net::unix::OwnedWriteHalf,
-    mut commands_rx: UnboundedReceiver<CommandArgument>,
-) -> Result<(), Box<dyn Error>> {
-    let mut buffer = Vec::new();
-
-    while let Some(command) = commands_rx.recv().await {
-        serde_json::to_writer(&mut buffer, &command)?;
-        buffer.push(b'\n');
-        write_half.write_all(&buffer).await?;
-
-        write_half.flush().await?;
-        buffer.clear();
+/// Short reason shown next to the state of the download.
+fn reason(failure: &DownloadFailure) -> String {
+    match (failure.status, failure.kind) {
+        (Some(status), _) => format!("HTTP {status} (attempt {})", failure.attempts),
+        // Flag the stalled downloads, so they stand out from the others retrying.
+        (None, FailureKind::Stalled) => format!(
+            "⚠ Stalled: {} (attempt {})",
+            failure.message, failure.attempts
+        ),
+        _ => format!(
+            "{}: {} (attempt {})",
+            failure.kind, failure.message, failure.attempts
+        ),
     }
-
-    Ok(())
 }
 
-pub async fn receive_progress(
-    read_half: tokio::net::unix::OwnedReadHalf,
-    realtime_tx: UnboundedSender<SingleDownload>,
-) -> Result<(), Box<dyn Error>> {
-    let mut reader = BufReader::new(read_half);
-    let mut line: String = String::new();
-
-    while let Ok(n) = reader.read_line(&mut line).await {
-        if n == 0 {
-            break;
+/// Connect to `$DLM_ADDRESS` over TCP with `$DLM_TOKEN`, or to the local socket.
+///
+/// A `tls://` address is connected to over TLS, trusting `$DLM_CERTIFICATE` if set.
+async fn connect() -> Result<Client, ClientError> {
+    let Ok(address) = env::var("DLM_ADDRESS") else {
+        return Client::connect(socket_path()).await;
+    };
+    let token = env::var("DLM_TOKEN").unwrap_or_default();
+
+    match address.strip_prefix("tls://") {
+        Some(address) => {
+            let certificate = env::var_os("DLM_CERTIFICATE").map(PathBuf::from);
+            Client::connect_tls(address, &token, certificate.as_deref()).await
         }
-
-        match serde_json::from_str::<Vec<SingleDownload>>(&line) {
-            Ok(data) => {
-                for each in data {
-                    if let Err(e) = realtime_tx.send(each) {
-                        error!("Error occurent while sending progress through the channel:{e}");
-                        break;
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Deserialization Error: {e:#?}");
-            }
-        }
-        line.clear();
+        None => Client::connect_tcp(&address, &token).await,
     }
-
-    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    match connect_socket().await {
-        Ok(stream) => {
-            let (read_half, write_half) = stream.into_split();
-
-            let (realtime_tx, realtime_rx) = mpsc::unbounded_channel::<SingleDownload>();
-            let (command_tx, command_rx) = mpsc::unbounded_channel::<CommandArgument>();
-
-            tokio::spawn(async move {
-                if let Err(e) = receive_progress(read_half, realtime_tx).await {
-                    error!("Failed to receive progress: {e}");
-                };
-            });
-
-            tokio::spawn(async move {
-                if let Err(e) = send_command(write_half, command_rx).await {
-                    error!("Failed to send command: {e}");
-                }
-            });
-
-            if let Err(e) = tui::run_tui(command_tx, realtime_rx).await {
+    match connect().await {
+        Ok(client) => {
+            if let Err(e) = tui::run_tui(client).await {
                 error!("Failed to run TUI: {:#?}", e);
             }
         }
@@ -1,6 +1,8 @@
-use color_eyre;
-
 use color_eyre::eyre::Ok;
+use dlm_control::Client;
+use dlm_control::Event as ControlEvent;
+use dlm_protocol::DownloadSnapshot;
+use dlm_protocol::Response;
 use ratatui::crossterm::event::{self, KeyCode, KeyModifiers};
 use ratatui::layout::{Constraint, Layout, Position};
 use ratatui::style::{Color, Modifier, Style, Stylize};
@@ -9,8 +11,8 @@ use ratatui::widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table,
 use ratatui::{DefaultTerminal, Frame};
 use serde::Deserialize;
 use serde::Serialize;
-use strum;
 use strum::IntoEnumIterator;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
@@ -22,8 +24,8 @@ use std::sync::RwLock;
 use std::time::Duration;
 use std::time::Instant;
 
+use crate::detail;
 use crate::CommandArgument;
-use crate::SingleDownload;
 
 #[derive(Default, Debug, Clone)]
 struct DownloadingTable {
@@ -33,6 +35,7 @@ struct DownloadingTable {
     progress: usize,
     status: String,
     total_length: usize,
+    detail: Option<String>,
 }
 
 impl DownloadingTable {
@@ -42,6 +45,7 @@ impl DownloadingTable {
         progress: usize,
         status: String,
         total_length: usize,
+        detail: Option<String>,
     ) -> Self {
         Self {
             id,
@@ -49,6 +53,7 @@ impl DownloadingTable {
             progress,
             status,
             total_length,
+            detail,
         }
     }
 }
@@ -72,6 +77,8 @@ pub enum CommandTab {
     Pause,
     Resume,
     Cancel,
+    Retry,
+    UpdateUrl,
 }
 
 impl CommandTab {
@@ -89,7 +96,9 @@ impl CommandTab {
 
 enum Event {
     Input(event::KeyEvent),
-    DownloadUpdate(SingleDownload),
+    DownloadUpdate(Box<DownloadSnapshot>),
+    /// Shown to the user, i.e. why a command failed.
+    Notice(String),
     Resize,
     Tick,
 }
@@ -111,7 +120,7 @@ fn handle_event(update_tx: UnboundedSender<Event>) {
             }
 
             if last_tick.elapsed() >= tick_rate {
-                if let Err(_) = update_tx.send(Event::Tick) {
+                if update_tx.send(Event::Tick).is_err() {
                     return;
                 }
                 last_tick = Instant::now();
@@ -120,27 +129,17 @@ fn handle_event(update_tx: UnboundedSender<Event>) {
     });
 }
 
-pub async fn run_tui(
-    command_tx: UnboundedSender<CommandArgument>,
-    mut realtime_rx: UnboundedReceiver<SingleDownload>,
-) -> color_eyre::Result<()> {
+pub async fn run_tui(client: Client) -> color_eyre::Result<()> {
     color_eyre::install()?;
     let terminal = ratatui::init();
 
     let mut app = App::new();
 
     let (update_tx, update_rx) = mpsc::unbounded_channel::<Event>();
+    let (command_tx, command_rx) = mpsc::unbounded_channel::<CommandArgument>();
     handle_event(update_tx.clone());
-
-    let tx = update_tx.clone();
-
-    tokio::spawn(async move {
-        loop {
-            if let Some(progress) = realtime_rx.recv().await {
-                tx.send(Event::DownloadUpdate(progress)).unwrap();
-            };
-        }
-    });
+    tokio::spawn(send_commands(client.clone(), command_rx, update_tx.clone()));
+    tokio::spawn(receive_events(client, update_tx));
 
     let app_result = app.run(terminal, command_tx, update_rx).await;
 
@@ -148,6 +147,67 @@ pub async fn run_tui(
     app_result
 }
 
+/// Send the commands to the server in order, telling the user why the failed ones failed.
+async fn send_commands(
+    client: Client,
+    mut command_rx: UnboundedReceiver<CommandArgument>,
+    tx: UnboundedSender<Event>,
+) {
+    while let Some(argument) = command_rx.recv().await {
+        let Some(command) = argument.command() else {
+            error!("Missing argument of the command");
+            continue;
+        };
+
+        let notices = match client.request(command).await {
+            Result::Ok(Response::Added { rejected, .. }) => rejected
+                .into_iter()
+                .map(|rejected| format!("Rejected {}: {}", rejected.url, rejected.message))
+                .collect(),
+            Result::Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        };
+
+        for notice in notices {
+            if tx.send(Event::Notice(notice)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Pass the changes of the downloads to the app.
+async fn receive_events(client: Client, tx: UnboundedSender<Event>) {
+    let mut events = client.subscribe();
+
+    loop {
+        let downloads = match events.recv().await {
+            Result::Ok(ControlEvent::Download(download)) => vec![download],
+            Result::Ok(ControlEvent::Snapshot(downloads)) => {
+                downloads.into_iter().map(Box::new).collect()
+            }
+            // Skipped changes are replaced with the current state of every download.
+            Err(RecvError::Lagged(_)) => match client.list().await {
+                Result::Ok(downloads) => downloads.into_iter().map(Box::new).collect(),
+                Err(e) => {
+                    error!("Failed to list the downloads: {e}");
+                    continue;
+                }
+            },
+            Err(RecvError::Closed) => {
+                let _ = tx.send(Event::Notice("Disconnected from the server".to_string()));
+                return;
+            }
+        };
+
+        for download in downloads {
+            if tx.send(Event::DownloadUpdate(download)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 struct HandleInput {
     input_value: String,
@@ -198,7 +258,12 @@ impl HandleInput {
         self.character_idx = 0;
     }
 
-    fn submit_message(&mut self, selected_tab: CommandTab) -> (Option<Vec<String>>, Option<usize>) {
+    fn submit_message(
+        &mut self,
+        selected_tab: CommandTab,
+    ) -> (Option<Vec<String>>, Option<usize>, Option<bool>) {
+        let mut delete = None;
+
         if selected_tab == CommandTab::Download {
             let data = self
                 .input_value
@@ -207,8 +272,10 @@ impl HandleInput {
                 .collect();
             self.messages = Some(data);
         } else {
-            if !self.input_value.is_empty() {
-                let parsed_value = self.input_value.trim().parse::<usize>();
+            let mut values = self.input_value.split_whitespace();
+
+            if let Some(value) = values.next() {
+                let parsed_value = value.parse::<usize>();
 
                 if let Err(e) = parsed_value {
                     error!("Failed to parse the value:{e}");
@@ -216,12 +283,19 @@ impl HandleInput {
                     self.id = Some(parsed_value.unwrap())
                 }
             }
+
+            // `-d` after the ID removes the partial file on cancel.
+            if selected_tab == CommandTab::Cancel {
+                delete = Some(values.any(|flag| flag == "-d" || flag == "--delete"));
+            } else if selected_tab == CommandTab::UpdateUrl {
+                self.messages = values.next().map(|url| vec![url.to_string()]);
+            }
         }
 
         self.input_value.clear();
         self.reset_cursor();
 
-        (self.messages.clone(), self.id)
+        (self.messages.clone(), self.id, delete)
     }
 
     fn delete_char(&mut self) {
@@ -246,6 +320,8 @@ struct App {
     selected_tab: CommandTab,
     table_data: Arc<RwLock<HashMap<u64, DownloadingTable>>>,
     begin_time: Instant,
+    /// Last reply of the server, i.e. why a url was rejected.
+    notice: Option<String>,
 }
 
 impl App {
@@ -255,6 +331,7 @@ impl App {
             selected_tab: CommandTab::Download,
             table_data: Arc::new(RwLock::new(HashMap::new())),
             begin_time: Instant::now(),
+            notice: None,
         }
     }
 
@@ -288,11 +365,12 @@ impl App {
                     }
 
                     (KeyCode::Enter, _) => {
-                        let (message, id) = self.input.submit_message(self.selected_tab);
+                        let (message, id, delete) = self.input.submit_message(self.selected_tab);
                         let command = CommandArgument {
                             command: self.selected_tab,
                             urls: message,
                             id,
+                            delete,
                         };
 
                         // Send the Input Commands to the Server for download
@@ -311,7 +389,11 @@ impl App {
                 }
 
                 Event::Tick => {}
+                Event::Notice(notice) => {
+                    self.notice = Some(notice);
+                }
                 Event::DownloadUpdate(progress) => {
+                    let detail = detail(&progress);
                     let mut table = self.table_data.write().unwrap();
                     table.insert(
                         progress.id as u64,
@@ -319,8 +401,9 @@ impl App {
                             progress.id as u64,
                             progress.url,
                             progress.progress,
-                            progress.state,
+                            progress.state.to_string(),
                             progress.total_length,
+                            detail,
                         ),
                     );
                 }
@@ -357,11 +440,15 @@ impl App {
         frame.render_widget(tabs, tab_area);
 
         // Paragraph Info top
-        let msg_one = vec![
+        let mut msg_one = vec![
             "Press ".into(),
             "Esc".bold().underlined(),
             " to quit".into(),
         ];
+        if let Some(notice) = &self.notice {
+            msg_one.push(". ".into());
+            msg_one.push(notice.clone().red());
+        }
         frame.render_widget(self.info_paragraph(msg_one), help_area_one);
 
         // Paragraph Info below
@@ -398,7 +485,7 @@ impl App {
                     "Downloading" => Style::default()
                         .fg(Color::LightBlue)
                         .add_modifier(Modifier::BOLD),
-                    "Completed" => Style::default()
+                    "Completed" | "UpToDate" => Style::default()
                         .fg(Color::LightGreen)
                         .add_modifier(Modifier::BOLD),
                     "Paused" => Style::default()
@@ -407,25 +494,38 @@ impl App {
                     "Canceled" => Style::default()
                         .fg(Color::LightRed)
                         .add_modifier(Modifier::BOLD),
+                    "Retrying" => Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                    "Failed" => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
                     _ => Style::default(),
                 };
 
-                let completed_progress: Option<Line<'static>> = if data.status == "Completed" {
-                    Some(self.progress_design(20, 100))
-                } else {
-                    None
+                // Show why the download is failing, or how it was verified, along with the status
+                let status = match (data.status.as_str(), &data.detail) {
+                    ("Failed" | "Retrying" | "Completed", Some(detail)) => {
+                        format!("{} - {detail}", data.status)
+                    }
+                    _ => data.status.to_string(),
                 };
 
+                let completed_progress: Option<Line<'static>> =
+                    if data.status == "Completed" || data.status == "UpToDate" {
+                        Some(self.progress_design(20, 100))
+                    } else {
+                        None
+                    };
+
                 // Create cells with proper styling
                 Row::new(vec![
                     Cell::from(id.to_string()),
                     Cell::from(data.name.to_string()),
-                    Cell::from(if completed_progress.is_some() {
-                        completed_progress.unwrap()
+                    Cell::from(if let Some(completed_progress) = completed_progress {
+                        completed_progress
                     } else {
                         self.progress_bar(data.progress, data.total_length)
                     }),
-                    Cell::from(Span::styled(data.status.to_string(), status_style)),
+                    Cell::from(Span::styled(status, status_style)),
                 ])
             })
             .collect();
@@ -434,9 +534,9 @@ impl App {
             rows,
             [
                 Constraint::Percentage(5),
+                Constraint::Percentage(25),
+                Constraint::Percentage(40),
                 Constraint::Percentage(30),
-                Constraint::Percentage(55),
-                Constraint::Percentage(10),
             ],
         )
         .header(header)
@@ -455,7 +555,7 @@ impl App {
     #[inline]
     fn progress_bar(&self, progress: usize, total_length: usize) -> Line<'_> {
         if total_length != 0 {
-            let percent = (progress as f64 / 100.0) as f64;
+            let percent = progress as f64 / 100.0;
             let filled = (percent * 20.0).round() as usize;
 
             self.progress_design(filled, progress)
@@ -496,7 +596,7 @@ impl App {
             } else {
                 Style::default().fg(Color::White)
             };
-            return Span::styled(tab.to_string(), style);
+            Span::styled(tab.to_string(), style)
         });
         Tabs::new(tab_titles)
             .padding("  ", "  ")
@@ -514,10 +614,12 @@ impl App {
     }
 
     #[inline]
-    fn input_paragraph(&self) -> Paragraph {
+    fn input_paragraph(&self) -> Paragraph<'_> {
         let input_value = if self.input.input_value.is_empty() {
             match self.selected_tab {
                 CommandTab::Download => "➤ Enter URL ",
+                CommandTab::Cancel => "➤ Enter ID (add -d to delete the file) ",
+                CommandTab::UpdateUrl => "➤ Enter ID and the new URL ",
                 _ => "➤ Enter ID ",
             }
         } else {